@@ -1,6 +1,12 @@
 import { getDb } from "./db";
 
-export type StreamEventType = "created" | "claimed" | "canceled" | "start_time_updated";
+export type StreamEventType =
+  | "created"
+  | "claimed"
+  | "canceled"
+  | "renounced"
+  | "start_time_updated"
+  | "recipient_transferred";
 
 export interface StreamEvent {
   id: number;
@@ -137,6 +137,7 @@ function processEvent(db: any, event: rpc.Api.EventResponse): void {
             token: value.token,
             startTime: value.start_time,
             endTime: value.end_time,
+            feeBps: value.fee_bps,
           },
         );
         break;
@@ -149,6 +150,7 @@ function processEvent(db: any, event: rpc.Api.EventResponse): void {
           timestamp,
           value.recipient,
           value.amount,
+          { fee: Number(value.fee) },
         );
         break;
 
@@ -159,6 +161,53 @@ function processEvent(db: any, event: rpc.Api.EventResponse): void {
           "canceled",
           timestamp,
           value.sender,
+          value.sender_refund,
+          {
+            recipientAmount: Number(value.recipient_amount),
+            fee: Number(value.fee),
+          },
+        );
+        break;
+
+      case "Renounced":
+        recordEventWithDb(
+          db,
+          value.stream_id.toString(),
+          "renounced",
+          timestamp,
+          value.recipient,
+          value.recipient_amount,
+          {
+            senderRefund: Number(value.sender_refund),
+            fee: Number(value.fee),
+          },
+        );
+        break;
+
+      case "Resched":
+        recordEventWithDb(
+          db,
+          value.stream_id.toString(),
+          "start_time_updated",
+          timestamp,
+          value.sender,
+          undefined,
+          {
+            oldStartAt: Number(value.old_start_time),
+            newStartAt: Number(value.start_time),
+          },
+        );
+        break;
+
+      case "Transfer":
+        recordEventWithDb(
+          db,
+          value.stream_id.toString(),
+          "recipient_transferred",
+          timestamp,
+          value.from,
+          undefined,
+          { from: value.from, to: value.to },
         );
         break;
     }
@@ -8,6 +8,7 @@ import {
   TimeoutInfinite,
   TransactionBuilder,
   Networks,
+  xdr,
 } from "@stellar/stellar-sdk";
 import { initDb, getDb } from "./db";
 import { recordEventWithDb } from "./eventHistory";
@@ -257,6 +258,14 @@ export async function createStream(input: StreamInput): Promise<StreamRecord> {
     nativeToScVal(input.totalAmount, { type: "i128" }),
     nativeToScVal(startAt, { type: "u64" }),
     nativeToScVal(endAt, { type: "u64" }),
+    // no cliff
+    xdr.ScVal.scvVoid(),
+    // nothing unlocked upfront
+    nativeToScVal(0, { type: "i128" }),
+    // not transferable
+    nativeToScVal(false),
+    // Cancelability::Always
+    xdr.ScVal.scvVec([xdr.ScVal.scvSymbol("Always")]),
   );
 
   // We have to build and send this tx. Wait, doing this properly via building is long:
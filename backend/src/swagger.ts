@@ -157,7 +157,14 @@ export const swaggerDocument = {
           },
           eventType: {
             type: "string",
-            enum: ["created", "claimed", "canceled", "start_time_updated"],
+            enum: [
+              "created",
+              "claimed",
+              "canceled",
+              "renounced",
+              "start_time_updated",
+              "recipient_transferred",
+            ],
             example: "created",
           },
           timestamp: {
@@ -82,7 +82,14 @@ export const updateStreamStartAtSchema = z.object({
   startAt: unixTimestampSchema,
 });
 
-const VALID_EVENT_TYPES = ["created", "claimed", "canceled", "start_time_updated"] as const;
+const VALID_EVENT_TYPES = [
+  "created",
+  "claimed",
+  "canceled",
+  "renounced",
+  "start_time_updated",
+  "recipient_transferred",
+] as const;
 
 export const listEventsQuerySchema = z.object({
   eventType: z
@@ -1,9 +1,143 @@
 #![no_std]
+#![allow(clippy::too_many_arguments)]
 
 use soroban_sdk::{
-    contract, contractimpl, contracttype, symbol_short, token::Client as TokenClient, Address, Env,
+    contract, contracterror, contractimpl, contracttype, symbol_short,
+    token::Client as TokenClient, Address, BytesN, Env, IntoVal, Map, TryFromVal, Val, Vec,
 };
 
+mod math;
+
+use math::{mul_wad, pow_wad, WAD};
+
+/// Version of this build. Bump it whenever a release needs `migrate` to run
+/// after `upgrade`.
+#[cfg(not(feature = "next-version"))]
+const CONTRACT_VERSION: u32 = 1;
+/// The upgrade tests run against this build as the next release.
+#[cfg(feature = "next-version")]
+const CONTRACT_VERSION: u32 = 2;
+
+/// Fees are in basis points of the amount paid to the recipient.
+const BPS_DENOMINATOR: i128 = 10_000;
+/// Highest fee the admin can set, 10%.
+const MAX_FEE_BPS: u32 = 1_000;
+
+/// Largest accepted curve exponent, in 18-decimal fixed point.
+const MAX_CURVE_EXPONENT: i128 = 32 * WAD;
+
+/// Approximate ledger close time, used to turn timestamps into TTLs.
+const LEDGER_SECONDS: u64 = 5;
+const DAY_IN_LEDGERS: u32 = 17_280;
+/// How long a stream entry outlives its `end_time`, so late claims still work.
+const STREAM_TTL_GRACE: u32 = 30 * DAY_IN_LEDGERS;
+const INSTANCE_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+/// Extension for persistent entries other than streams: the id counter,
+/// amendments, pending sender transfers and operator approvals.
+const ENTRY_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+const ENTRY_LIFETIME_THRESHOLD: u32 = ENTRY_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tranche {
+    pub timestamp: u64,
+    pub amount: i128,
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    /// The stream's `cancelability` forbids canceling or pausing it now.
+    StreamNotCancelable = 1,
+    StreamNotFound = 2,
+    /// An amount is zero, negative or out of range.
+    InvalidAmount = 3,
+    /// Times are out of order, in the past, or outside the stream.
+    InvalidTimeRange = 4,
+    InsufficientBalance = 5,
+    RecipientMismatch = 6,
+    SenderMismatch = 7,
+    AmountExceedsClaimable = 8,
+    StreamCanceled = 9,
+    /// The operation does not apply to this kind of stream.
+    UnsupportedStreamKind = 10,
+    StreamPaused = 11,
+    StreamNotPaused = 12,
+    StreamEnded = 13,
+    StreamStarted = 14,
+    /// Tranches, segments or the curve exponent do not describe a valid
+    /// schedule.
+    InvalidSchedule = 15,
+    EmptyBatch = 16,
+    NoPendingAmendment = 17,
+    /// Only the party that did not propose an amendment may accept it.
+    NotCounterparty = 18,
+    AmendmentExpired = 19,
+    /// The new total would take back tokens that have already vested.
+    BelowVestedAmount = 20,
+    /// The caller is neither the sender nor the recipient.
+    NotStreamParty = 21,
+    StreamNotTransferable = 22,
+    /// The new address is the same as the current one.
+    SameAddress = 23,
+    NoPendingSenderTransfer = 24,
+    OperatorNotApproved = 25,
+    AlreadyInitialized = 26,
+    /// `initialize` has not been called, so there is no admin.
+    NotInitialized = 27,
+    /// Stored data is already at this build's version.
+    AlreadyMigrated = 28,
+    /// The admin has paused the contract for this kind of call.
+    Paused = 29,
+    NotPaused = 30,
+    /// The fee rate is above `MAX_FEE_BPS`.
+    FeeTooHigh = 31,
+    /// Per-token fees need a treasury, set with `set_fee` first.
+    FeeNotConfigured = 32,
+}
+
+/// Whether, and from when, the sender may cancel a stream.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cancelability {
+    Always,
+    /// Irrevocable: the stream can never be canceled, paused or rescheduled.
+    Never,
+    /// Cancelable (and pausable or reschedulable) only from the given
+    /// timestamp on.
+    After(u64),
+}
+
+/// Piece of a segmented schedule. The segment streams `amount` linearly from
+/// the end of the previous segment (or `start_time`) until its `end_time`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub end_time: u64,
+    pub amount: i128,
+}
+
+/// Vesting schedule of a stream between `start_time` and `end_time`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamKind {
+    /// Vests continuously per second.
+    Linear,
+    /// Vests in discrete steps, each tranche unlocking at its timestamp.
+    Tranched(Vec<Tranche>),
+    /// Piecewise-linear, each segment with its own rate.
+    Segmented(Vec<Segment>),
+    /// Vests as `(elapsed / duration) ^ exponent`, the exponent being an
+    /// 18-decimal fixed-point number (above one back-loads, below one
+    /// front-loads).
+    Curve(i128),
+    /// Open-ended: pays a fixed amount per second until the deposited
+    /// balance runs out. `end_time` is derived from the balance and rate.
+    Rate(i128),
+}
+
 #[contracttype]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Stream {
@@ -14,13 +148,84 @@ pub struct Stream {
     pub claimed_amount: i128,
     pub start_time: u64,
     pub end_time: u64,
+    pub cliff_time: Option<u64>,
+    pub unlock_amount: i128,
+    pub kind: StreamKind,
+    pub paused_at: Option<u64>,
+    /// Whether the recipient may hand the stream over to another address.
+    pub transferable: bool,
+    pub cancelability: Cancelability,
     pub canceled: bool,
+    /// Set once a canceled stream has paid out everything it owes, so
+    /// nothing for it is left in escrow.
+    pub settled: bool,
+    /// Protocol fee rate, fixed when the stream is created. It is taken
+    /// from every payout to the recipient and sent to the treasury.
+    pub fee_bps: u32,
+    /// Total fees taken from this stream so far.
+    pub fees_paid: i128,
+}
+
+/// New terms for a linear stream, waiting for the counterparty to accept.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Amendment {
+    pub proposer: Address,
+    pub total_amount: i128,
+    pub end_time: u64,
+    pub expires_at: u64,
+    /// Funds the sender already moved into escrow for a proposed increase.
+    pub escrowed: i128,
+}
+
+/// One stream in a `create_streams` batch.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamEntry {
+    pub recipient: Address,
+    pub total_amount: i128,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+/// Result of `withdraw_max`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClaimReceipt {
+    /// Tokens claimed by this call, including the fee.
+    pub amount: i128,
+    /// Part of `amount` sent to the treasury.
+    pub fee: i128,
+    /// The stream's `claimed_amount` after this call.
+    pub claimed_amount: i128,
+}
+
+/// Default protocol fee and where fees are sent.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeeConfig {
+    pub fee_bps: u32,
+    pub treasury: Address,
 }
 
 #[contracttype]
 enum DataKey {
     NextStreamId,
     Stream(u64),
+    Amendment(u64),
+    PendingSender(u64),
+    /// (recipient, operator) pairs allowed to claim on the recipient's behalf.
+    Operator(Address, Address),
+    /// Instance storage.
+    Admin,
+    /// Instance storage: version of the build that last migrated the data.
+    Version,
+    /// Instance storage: present while the admin pause is on.
+    ContractPause,
+    /// Instance storage.
+    FeeConfig,
+    /// Instance storage: fee rate overriding the default for one token.
+    TokenFee(Address),
 }
 
 #[contracttype]
@@ -33,6 +238,9 @@ pub struct StreamCreated {
     pub total_amount: i128,
     pub start_time: u64,
     pub end_time: u64,
+    pub cliff_time: Option<u64>,
+    pub unlock_amount: i128,
+    pub fee_bps: u32,
 }
 
 #[contracttype]
@@ -40,7 +248,9 @@ pub struct StreamCreated {
 pub struct StreamClaimed {
     pub stream_id: u64,
     pub recipient: Address,
+    /// Claimed from the stream; the recipient receives `amount - fee`.
     pub amount: i128,
+    pub fee: i128,
 }
 
 #[contracttype]
@@ -48,6 +258,190 @@ pub struct StreamClaimed {
 pub struct StreamCanceled {
     pub stream_id: u64,
     pub sender: Address,
+    pub sender_refund: i128,
+    /// Vested balance paid to the recipient by the cancel itself.
+    pub recipient_amount: i128,
+    /// Protocol fee included in `recipient_amount`.
+    pub fee: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamDeposited {
+    pub stream_id: u64,
+    pub sender: Address,
+    pub amount: i128,
+    pub end_time: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamPaused {
+    pub stream_id: u64,
+    pub sender: Address,
+    pub paused_at: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamResumed {
+    pub stream_id: u64,
+    pub sender: Address,
+    pub end_time: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamToppedUp {
+    pub stream_id: u64,
+    pub sender: Address,
+    pub amount: i128,
+    pub total_amount: i128,
+    pub end_time: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamRescheduled {
+    pub stream_id: u64,
+    pub sender: Address,
+    pub old_start_time: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmendmentProposed {
+    pub stream_id: u64,
+    pub proposer: Address,
+    pub total_amount: i128,
+    pub end_time: u64,
+    pub expires_at: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmendmentCanceled {
+    pub stream_id: u64,
+    pub caller: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamAmended {
+    pub stream_id: u64,
+    pub accepted_by: Address,
+    pub total_amount: i128,
+    pub end_time: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecipientTransferred {
+    pub stream_id: u64,
+    pub from: Address,
+    pub to: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SenderTransferProposed {
+    pub stream_id: u64,
+    pub sender: Address,
+    pub new_sender: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SenderTransferred {
+    pub stream_id: u64,
+    pub from: Address,
+    pub to: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamRenounced {
+    pub stream_id: u64,
+    pub recipient: Address,
+    pub recipient_amount: i128,
+    pub sender_refund: i128,
+    /// Protocol fee included in `recipient_amount`.
+    pub fee: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OperatorApproved {
+    pub recipient: Address,
+    pub operator: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OperatorRevoked {
+    pub recipient: Address,
+    pub operator: Address,
+}
+
+/// Admin pause in effect. Creating streams and adding funds or new terms to
+/// existing ones is always blocked; claims and cancels only if flagged.
+/// Vesting is not affected and keeps accruing throughout.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractPause {
+    pub claims: bool,
+    pub cancels: bool,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractPaused {
+    pub admin: Address,
+    pub claims: bool,
+    pub cancels: bool,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractUnpaused {
+    pub admin: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeeUpdated {
+    pub fee_bps: u32,
+    pub treasury: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenFeeUpdated {
+    pub token: Address,
+    pub fee_bps: Option<u32>,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdminChanged {
+    pub from: Address,
+    pub to: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractUpgraded {
+    pub admin: Address,
+    pub new_wasm_hash: BytesN<32>,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractMigrated {
+    pub from_version: u32,
+    pub to_version: u32,
 }
 
 #[contract]
@@ -63,206 +457,1956 @@ impl StellarStreamContract {
         total_amount: i128,
         start_time: u64,
         end_time: u64,
-    ) -> u64 {
+        cliff_time: Option<u64>,
+        unlock_amount: i128,
+        transferable: bool,
+        cancelability: Cancelability,
+    ) -> Result<u64, Error> {
+        require_not_paused(&env)?;
+
         sender.require_auth();
 
         if total_amount <= 0 {
-            panic!("total_amount must be positive");
+            return Err(Error::InvalidAmount);
         }
         if end_time <= start_time {
-            panic!("end_time must be greater than start_time");
+            return Err(Error::InvalidTimeRange);
         }
-
-        // checks sebder balance.
-        let token_client = TokenClient::new(&env, &token);
-        let sender_balance = token_client.balance(&sender);
-        if sender_balance < total_amount {
-            panic!("insufficient sender balance");
+        if let Some(cliff) = cliff_time {
+            if cliff < start_time || cliff > end_time {
+                return Err(Error::InvalidTimeRange);
+            }
+        }
+        if unlock_amount < 0 || unlock_amount > total_amount {
+            return Err(Error::InvalidAmount);
         }
-
-        // escrow = transfer total_amount from sender into this contract
-        let contract_address = env.current_contract_address();
-        token_client.transfer(&sender, &contract_address, &total_amount);
-
-        let mut next_id: u64 = env
-            .storage()
-            .persistent()
-            .get(&DataKey::NextStreamId)
-            .unwrap_or(0);
-        next_id += 1;
 
         let stream = Stream {
-            sender: sender.clone(),
-            recipient: recipient.clone(),
-            token: token.clone(),
+            sender,
+            recipient,
+            token,
             total_amount,
             claimed_amount: 0,
             start_time,
             end_time,
+            cliff_time,
+            unlock_amount,
+            kind: StreamKind::Linear,
+            paused_at: None,
+            transferable,
+            cancelability,
             canceled: false,
+            settled: false,
+            fee_bps: 0,
+            fees_paid: 0,
         };
 
-        env.storage()
-            .persistent()
-            .set(&DataKey::NextStreamId, &next_id);
-        env.storage()
-            .persistent()
-            .set(&DataKey::Stream(next_id), &stream);
-
-        env.events().publish(
-            (symbol_short!("Stream"), symbol_short!("Created")),
-            StreamCreated {
-                stream_id: next_id,
-                sender,
-                recipient,
-                token,
-                total_amount,
-                start_time,
-                end_time,
-            },
-        );
-
-        next_id
-    }
-
-    pub fn get_stream(env: Env, stream_id: u64) -> Stream {
-        read_stream(&env, stream_id)
+        open_stream(&env, stream)
     }
 
-    pub fn get_next_stream_id(env: Env) -> u64 {
-        env.storage()
-            .persistent()
-            .get(&DataKey::NextStreamId)
-            .unwrap_or(0)
-    }
+    /// Creates one linear stream per entry, all funded by `sender` in `token`
+    /// with a single escrow transfer. Every entry is validated before any
+    /// funds move. Returns the new ids in entry order.
+    pub fn create_streams(
+        env: Env,
+        sender: Address,
+        token: Address,
+        entries: Vec<StreamEntry>,
+        transferable: bool,
+        cancelability: Cancelability,
+    ) -> Result<Vec<u64>, Error> {
+        require_not_paused(&env)?;
 
-    pub fn claimable(env: Env, stream_id: u64, at_time: u64) -> i128 {
-        let stream = read_stream(&env, stream_id);
-        let vested = vested_amount(&stream, at_time);
-        let claimable = vested - stream.claimed_amount;
-        if claimable < 0 {
-            0
-        } else {
-            claimable
-        }
-    }
+        sender.require_auth();
 
-    pub fn claim(env: Env, stream_id: u64, recipient: Address, amount: i128) -> i128 {
-        if amount <= 0 {
-            panic!("amount must be positive");
+        if entries.is_empty() {
+            return Err(Error::EmptyBatch);
         }
 
-        let mut stream = read_stream(&env, stream_id);
-        if stream.recipient != recipient {
-            panic!("recipient mismatch");
+        let mut total: i128 = 0;
+        for entry in entries.iter() {
+            if entry.total_amount <= 0 {
+                return Err(Error::InvalidAmount);
+            }
+            if entry.end_time <= entry.start_time {
+                return Err(Error::InvalidTimeRange);
+            }
+            total = total
+                .checked_add(entry.total_amount)
+                .ok_or(Error::InvalidAmount)?;
         }
-        recipient.require_auth();
 
-        let now = env.ledger().timestamp();
-        let claimable_now = Self::claimable(env.clone(), stream_id, now);
+        escrow(&env, &sender, &token, total)?;
 
-        // amount claimed cannot exceed vested amount
-        if amount > claimable_now {
-            panic!("amount exceeds claimable");
+        let mut ids = Vec::new(&env);
+        for entry in entries.iter() {
+            let stream = Stream {
+                sender: sender.clone(),
+                recipient: entry.recipient,
+                token: token.clone(),
+                total_amount: entry.total_amount,
+                claimed_amount: 0,
+                start_time: entry.start_time,
+                end_time: entry.end_time,
+                cliff_time: None,
+                unlock_amount: 0,
+                kind: StreamKind::Linear,
+                paused_at: None,
+                transferable,
+                cancelability: cancelability.clone(),
+                canceled: false,
+                settled: false,
+                fee_bps: 0,
+                fees_paid: 0,
+            };
+            ids.push_back(store_new_stream(&env, stream));
         }
 
-        // transfer tokens from contract escrow to recipient
-        let token_client = TokenClient::new(&env, &stream.token);
-        let contract_address = env.current_contract_address();
-        token_client.transfer(&contract_address, &recipient, &amount);
-
-        // Update accounting after successful transfer
-        stream.claimed_amount += amount;
-        env.storage()
-            .persistent()
-            .set(&DataKey::Stream(stream_id), &stream);
-
-        env.events().publish(
-            (symbol_short!("Stream"), symbol_short!("Claimed")),
-            StreamClaimed {
-                stream_id,
-                recipient,
-                amount,
-            },
-        );
-
-        amount
+        Ok(ids)
     }
 
-    pub fn cancel(env: Env, stream_id: u64, sender: Address) {
-        let mut stream = read_stream(&env, stream_id);
-        if stream.sender != sender {
-            panic!("sender mismatch");
-        }
+    /// Creates a stream that unlocks in discrete steps. The stream ends at
+    /// the last tranche and the tranche amounts must add up to `total_amount`.
+    pub fn create_tranched_stream(
+        env: Env,
+        sender: Address,
+        recipient: Address,
+        token: Address,
+        total_amount: i128,
+        start_time: u64,
+        tranches: Vec<Tranche>,
+        transferable: bool,
+        cancelability: Cancelability,
+    ) -> Result<u64, Error> {
+        require_not_paused(&env)?;
+
         sender.require_auth();
 
-        if stream.canceled {
-            return;
+        if total_amount <= 0 {
+            return Err(Error::InvalidAmount);
         }
-
-        let now = env.ledger().timestamp();
-        stream.canceled = true;
-
-        // compute vested BEFORE truncating end_time
-        let vested = vested_amount(&stream, now);
-        let sender_refund = stream.total_amount - vested;
-
-        // truncate end_time so recipient can't claim past cancel point
-        let min_end = if now > stream.start_time {
-            now
-        } else {
-            stream.start_time
-        };
-        if min_end < stream.end_time {
-            stream.end_time = min_end;
-            // Adjust total_amount to match the vested amount at cancel time
-            // This ensures that the vested calculation remains correct after truncation
-            stream.total_amount = vested;
+        if tranches.is_empty() {
+            return Err(Error::InvalidSchedule);
         }
 
-        if sender_refund > 0 {
-            let token_client = TokenClient::new(&env, &stream.token);
-            let contract_address = env.current_contract_address();
-            token_client.transfer(&contract_address, &sender, &sender_refund);
+        let mut sum: i128 = 0;
+        let mut end_time = start_time;
+        for (i, tranche) in tranches.iter().enumerate() {
+            if tranche.amount <= 0 {
+                return Err(Error::InvalidAmount);
+            }
+            if tranche.timestamp < end_time || (i > 0 && tranche.timestamp == end_time) {
+                return Err(Error::InvalidTimeRange);
+            }
+            sum = sum
+                .checked_add(tranche.amount)
+                .ok_or(Error::InvalidAmount)?;
+            end_time = tranche.timestamp;
+        }
+        if sum != total_amount {
+            return Err(Error::InvalidSchedule);
         }
 
-        env.storage()
-            .persistent()
-            .set(&DataKey::Stream(stream_id), &stream);
+        if end_time <= start_time {
+            return Err(Error::InvalidTimeRange);
+        }
 
-        env.events().publish(
+        let stream = Stream {
+            sender,
+            recipient,
+            token,
+            total_amount,
+            claimed_amount: 0,
+            start_time,
+            end_time,
+            cliff_time: None,
+            unlock_amount: 0,
+            kind: StreamKind::Tranched(tranches),
+            paused_at: None,
+            transferable,
+            cancelability,
+            canceled: false,
+            settled: false,
+            fee_bps: 0,
+            fees_paid: 0,
+        };
+
+        open_stream(&env, stream)
+    }
+
+    /// Creates a piecewise-linear stream. Segments are consecutive, the first
+    /// one starting at `start_time`, and the stream ends with the last one.
+    pub fn create_segmented_stream(
+        env: Env,
+        sender: Address,
+        recipient: Address,
+        token: Address,
+        total_amount: i128,
+        start_time: u64,
+        segments: Vec<Segment>,
+        transferable: bool,
+        cancelability: Cancelability,
+    ) -> Result<u64, Error> {
+        require_not_paused(&env)?;
+
+        sender.require_auth();
+
+        if total_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if segments.is_empty() {
+            return Err(Error::InvalidSchedule);
+        }
+
+        let mut sum: i128 = 0;
+        let mut end_time = start_time;
+        for segment in segments.iter() {
+            if segment.amount < 0 {
+                return Err(Error::InvalidAmount);
+            }
+            if segment.end_time <= end_time {
+                return Err(Error::InvalidTimeRange);
+            }
+            sum = sum
+                .checked_add(segment.amount)
+                .ok_or(Error::InvalidAmount)?;
+            end_time = segment.end_time;
+        }
+        if sum != total_amount {
+            return Err(Error::InvalidSchedule);
+        }
+
+        let stream = Stream {
+            sender,
+            recipient,
+            token,
+            total_amount,
+            claimed_amount: 0,
+            start_time,
+            end_time,
+            cliff_time: None,
+            unlock_amount: 0,
+            kind: StreamKind::Segmented(segments),
+            paused_at: None,
+            transferable,
+            cancelability,
+            canceled: false,
+            settled: false,
+            fee_bps: 0,
+            fees_paid: 0,
+        };
+
+        open_stream(&env, stream)
+    }
+
+    /// Creates a stream vesting along `(elapsed / duration) ^ exponent`.
+    /// `exponent` is 18-decimal fixed point, so `1e18` is a linear stream.
+    pub fn create_curve_stream(
+        env: Env,
+        sender: Address,
+        recipient: Address,
+        token: Address,
+        total_amount: i128,
+        start_time: u64,
+        end_time: u64,
+        exponent: i128,
+        transferable: bool,
+        cancelability: Cancelability,
+    ) -> Result<u64, Error> {
+        require_not_paused(&env)?;
+
+        sender.require_auth();
+
+        if total_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if end_time <= start_time {
+            return Err(Error::InvalidTimeRange);
+        }
+        if exponent <= 0 || exponent > MAX_CURVE_EXPONENT {
+            return Err(Error::InvalidSchedule);
+        }
+
+        let stream = Stream {
+            sender,
+            recipient,
+            token,
+            total_amount,
+            claimed_amount: 0,
+            start_time,
+            end_time,
+            cliff_time: None,
+            unlock_amount: 0,
+            kind: StreamKind::Curve(exponent),
+            paused_at: None,
+            transferable,
+            cancelability,
+            canceled: false,
+            settled: false,
+            fee_bps: 0,
+            fees_paid: 0,
+        };
+
+        open_stream(&env, stream)
+    }
+
+    /// Creates an open-ended stream paying `rate_per_second` from
+    /// `start_time` until `deposit_amount` (and later deposits) are used up.
+    pub fn create_rate_stream(
+        env: Env,
+        sender: Address,
+        recipient: Address,
+        token: Address,
+        rate_per_second: i128,
+        start_time: u64,
+        deposit_amount: i128,
+        transferable: bool,
+        cancelability: Cancelability,
+    ) -> Result<u64, Error> {
+        require_not_paused(&env)?;
+
+        sender.require_auth();
+
+        if rate_per_second <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if deposit_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let stream = Stream {
+            sender,
+            recipient,
+            token,
+            total_amount: deposit_amount,
+            claimed_amount: 0,
+            start_time,
+            end_time: depletion_time(start_time, deposit_amount, rate_per_second)?,
+            cliff_time: None,
+            unlock_amount: 0,
+            kind: StreamKind::Rate(rate_per_second),
+            paused_at: None,
+            transferable,
+            cancelability,
+            canceled: false,
+            settled: false,
+            fee_bps: 0,
+            fees_paid: 0,
+        };
+
+        open_stream(&env, stream)
+    }
+
+    pub fn get_stream(env: Env, stream_id: u64) -> Result<Stream, Error> {
+        read_stream(&env, stream_id)
+    }
+
+    /// Extends the storage TTL of a stream so it stays live until after its
+    /// `end_time`. Anyone may call this.
+    pub fn bump_stream(env: Env, stream_id: u64) -> Result<(), Error> {
+        // reading a stream extends its TTL
+        read_stream(&env, stream_id)?;
+
+        Ok(())
+    }
+
+    pub fn get_next_stream_id(env: Env) -> u64 {
+        read_entry(&env, &DataKey::NextStreamId).unwrap_or(0)
+    }
+
+    pub fn claimable(env: Env, stream_id: u64, at_time: u64) -> Result<i128, Error> {
+        let stream = read_stream(&env, stream_id)?;
+        let vested = vested_amount(&stream, at_time);
+        let claimable = vested - stream.claimed_amount;
+        if claimable < 0 {
+            Ok(0)
+        } else {
+            Ok(claimable)
+        }
+    }
+
+    /// Claims `amount` for the stream's recipient. `caller` is either the
+    /// recipient or an operator the recipient approved; funds always go to
+    /// the recipient.
+    pub fn claim(env: Env, stream_id: u64, caller: Address, amount: i128) -> Result<i128, Error> {
+        require_claims_open(&env)?;
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.recipient != caller
+            && !Self::is_operator(env.clone(), stream.recipient.clone(), caller.clone())
+        {
+            return Err(Error::RecipientMismatch);
+        }
+        caller.require_auth();
+        let recipient = stream.recipient.clone();
+
+        let now = env.ledger().timestamp();
+        let claimable_now = Self::claimable(env.clone(), stream_id, now)?;
+
+        // amount claimed cannot exceed vested amount
+        if amount > claimable_now {
+            return Err(Error::AmountExceedsClaimable);
+        }
+
+        let fee = record_claim(&mut stream, amount);
+
+        // transfer tokens from contract escrow to recipient
+        let token_client = TokenClient::new(&env, &stream.token);
+        let contract_address = env.current_contract_address();
+        token_client.transfer(&contract_address, &recipient, &(amount - fee));
+        pay_fee(&env, &stream.token, fee);
+
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Claimed")),
+            StreamClaimed {
+                stream_id,
+                recipient,
+                amount,
+                fee,
+            },
+        );
+
+        Ok(amount)
+    }
+
+    /// Claims everything vested at the current ledger time and sends it to
+    /// `destination`. Returns a zero receipt instead of failing when
+    /// nothing is claimable.
+    pub fn withdraw_max(
+        env: Env,
+        stream_id: u64,
+        recipient: Address,
+        destination: Address,
+    ) -> Result<ClaimReceipt, Error> {
+        require_claims_open(&env)?;
+
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.recipient != recipient {
+            return Err(Error::RecipientMismatch);
+        }
+        recipient.require_auth();
+
+        let now = env.ledger().timestamp();
+        let amount = Self::claimable(env.clone(), stream_id, now)?;
+        if amount == 0 {
+            return Ok(ClaimReceipt {
+                amount,
+                fee: 0,
+                claimed_amount: stream.claimed_amount,
+            });
+        }
+
+        let fee = record_claim(&mut stream, amount);
+
+        let token_client = TokenClient::new(&env, &stream.token);
+        token_client.transfer(
+            &env.current_contract_address(),
+            &destination,
+            &(amount - fee),
+        );
+        pay_fee(&env, &stream.token, fee);
+
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Claimed")),
+            StreamClaimed {
+                stream_id,
+                recipient,
+                amount,
+                fee,
+            },
+        );
+
+        Ok(ClaimReceipt {
+            amount,
+            fee,
+            claimed_amount: stream.claimed_amount,
+        })
+    }
+
+    /// Claims everything vested now from each stream in `stream_ids` and pays
+    /// it out with one transfer per token. Returns the amount claimed from
+    /// each stream, in order.
+    pub fn claim_many(
+        env: Env,
+        recipient: Address,
+        stream_ids: Vec<u64>,
+    ) -> Result<Vec<i128>, Error> {
+        require_claims_open(&env)?;
+
+        recipient.require_auth();
+
+        let now = env.ledger().timestamp();
+        let mut amounts = Vec::new(&env);
+        let mut payouts: Map<Address, i128> = Map::new(&env);
+        let mut fees: Map<Address, i128> = Map::new(&env);
+        for stream_id in stream_ids.iter() {
+            let mut stream = read_stream(&env, stream_id)?;
+            if stream.recipient != recipient {
+                return Err(Error::RecipientMismatch);
+            }
+
+            let amount = Self::claimable(env.clone(), stream_id, now)?;
+            amounts.push_back(amount);
+            if amount == 0 {
+                continue;
+            }
+
+            let fee = record_claim(&mut stream, amount);
+            write_stream(&env, stream_id, &stream);
+            let owed = payouts.get(stream.token.clone()).unwrap_or(0);
+            payouts.set(stream.token.clone(), owed + amount - fee);
+            let owed_fee = fees.get(stream.token.clone()).unwrap_or(0);
+            fees.set(stream.token, owed_fee + fee);
+
+            env.events().publish(
+                (symbol_short!("Stream"), symbol_short!("Claimed")),
+                StreamClaimed {
+                    stream_id,
+                    recipient: recipient.clone(),
+                    amount,
+                    fee,
+                },
+            );
+        }
+
+        let contract_address = env.current_contract_address();
+        for (token, amount) in payouts.iter() {
+            TokenClient::new(&env, &token).transfer(&contract_address, &recipient, &amount);
+        }
+        for (token, fee) in fees.iter() {
+            pay_fee(&env, &token, fee);
+        }
+
+        Ok(amounts)
+    }
+
+    /// Cancels a stream and refunds the unvested balance to the sender.
+    /// With `settle` the recipient's vested but unclaimed balance is paid out
+    /// in the same call; otherwise it stays claimable.
+    pub fn cancel(env: Env, stream_id: u64, sender: Address, settle: bool) -> Result<(), Error> {
+        require_cancels_open(&env)?;
+
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.sender != sender {
+            return Err(Error::SenderMismatch);
+        }
+        sender.require_auth();
+
+        if stream.canceled {
+            return Ok(());
+        }
+
+        let (sender_refund, recipient_amount, fee) =
+            cancel_stream(&env, stream_id, &mut stream, settle)?;
+        let token_client = TokenClient::new(&env, &stream.token);
+        let contract_address = env.current_contract_address();
+        if sender_refund > 0 {
+            token_client.transfer(&contract_address, &sender, &sender_refund);
+        }
+        if recipient_amount > 0 {
+            token_client.transfer(
+                &contract_address,
+                &stream.recipient,
+                &(recipient_amount - fee),
+            );
+        }
+        pay_fee(&env, &stream.token, fee);
+
+        env.events().publish(
             (symbol_short!("Stream"), symbol_short!("Canceled")),
-            StreamCanceled { stream_id, sender },
+            StreamCanceled {
+                stream_id,
+                sender,
+                sender_refund,
+                recipient_amount,
+                fee,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Cancels every stream in `stream_ids` and refunds the sender with one
+    /// transfer per token. Streams that are already canceled are skipped.
+    /// `settle` works as in `cancel`, with one payout per recipient and token.
+    pub fn cancel_many(
+        env: Env,
+        sender: Address,
+        stream_ids: Vec<u64>,
+        settle: bool,
+    ) -> Result<(), Error> {
+        require_cancels_open(&env)?;
+
+        sender.require_auth();
+
+        let mut refunds: Map<Address, i128> = Map::new(&env);
+        let mut payouts: Map<(Address, Address), i128> = Map::new(&env);
+        let mut fees: Map<Address, i128> = Map::new(&env);
+        for stream_id in stream_ids.iter() {
+            let mut stream = read_stream(&env, stream_id)?;
+            if stream.sender != sender {
+                return Err(Error::SenderMismatch);
+            }
+            if stream.canceled {
+                continue;
+            }
+
+            let (sender_refund, recipient_amount, fee) =
+                cancel_stream(&env, stream_id, &mut stream, settle)?;
+            let owed = refunds.get(stream.token.clone()).unwrap_or(0);
+            refunds.set(stream.token.clone(), owed + sender_refund);
+            if recipient_amount > 0 {
+                let owed_fee = fees.get(stream.token.clone()).unwrap_or(0);
+                fees.set(stream.token.clone(), owed_fee + fee);
+                let key = (stream.token, stream.recipient);
+                let owed = payouts.get(key.clone()).unwrap_or(0);
+                payouts.set(key, owed + recipient_amount - fee);
+            }
+
+            env.events().publish(
+                (symbol_short!("Stream"), symbol_short!("Canceled")),
+                StreamCanceled {
+                    stream_id,
+                    sender: sender.clone(),
+                    sender_refund,
+                    recipient_amount,
+                    fee,
+                },
+            );
+        }
+
+        let contract_address = env.current_contract_address();
+        for (token, amount) in refunds.iter() {
+            if amount > 0 {
+                TokenClient::new(&env, &token).transfer(&contract_address, &sender, &amount);
+            }
+        }
+        for ((token, recipient), amount) in payouts.iter() {
+            TokenClient::new(&env, &token).transfer(&contract_address, &recipient, &amount);
+        }
+        for (token, fee) in fees.iter() {
+            pay_fee(&env, &token, fee);
+        }
+
+        Ok(())
+    }
+
+    /// Lets the recipient give a stream back. With `claim_vested` the vested
+    /// but unclaimed balance is paid out first, otherwise it is forfeited;
+    /// everything else is refunded to the sender.
+    pub fn renounce(
+        env: Env,
+        stream_id: u64,
+        recipient: Address,
+        claim_vested: bool,
+    ) -> Result<(), Error> {
+        require_cancels_open(&env)?;
+
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.recipient != recipient {
+            return Err(Error::RecipientMismatch);
+        }
+        recipient.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+
+        let now = env.ledger().timestamp();
+        let recipient_amount = if claim_vested {
+            vested_amount(&stream, now) - stream.claimed_amount
+        } else {
+            0
+        };
+        if recipient_amount > 0 {
+            require_claims_open(&env)?;
+        }
+        let kept = stream.claimed_amount + recipient_amount;
+        let sender_refund = stream.total_amount - kept;
+
+        drop_amendment(&env, stream_id, &stream);
+        close_stream(&mut stream, now, kept);
+        let fee = record_claim(&mut stream, recipient_amount);
+
+        let token_client = TokenClient::new(&env, &stream.token);
+        let contract_address = env.current_contract_address();
+        if recipient_amount > 0 {
+            token_client.transfer(&contract_address, &recipient, &(recipient_amount - fee));
+        }
+        pay_fee(&env, &stream.token, fee);
+        if sender_refund > 0 {
+            token_client.transfer(&contract_address, &stream.sender, &sender_refund);
+        }
+
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Renounced")),
+            StreamRenounced {
+                stream_id,
+                recipient,
+                recipient_amount,
+                sender_refund,
+                fee,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Adds `amount` to a linear stream and optionally moves its end time
+    /// out. What has vested so far is kept; the rest of the balance streams
+    /// from now until the (new) end time.
+    pub fn top_up(
+        env: Env,
+        stream_id: u64,
+        sender: Address,
+        amount: i128,
+        new_end_time: Option<u64>,
+    ) -> Result<(), Error> {
+        require_not_paused(&env)?;
+
+        if amount < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if amount == 0 && new_end_time.is_none() {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.sender != sender {
+            return Err(Error::SenderMismatch);
+        }
+        sender.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+        if stream.kind != StreamKind::Linear {
+            return Err(Error::UnsupportedStreamKind);
+        }
+        if let Some(end_time) = new_end_time {
+            if end_time < stream.end_time {
+                return Err(Error::InvalidTimeRange);
+            }
+        }
+
+        let total_amount = stream
+            .total_amount
+            .checked_add(amount)
+            .ok_or(Error::InvalidAmount)?;
+        if amount > 0 {
+            escrow(&env, &sender, &stream.token, amount)?;
+        }
+
+        let now = env.ledger().timestamp();
+        rebase(&mut stream, now);
+        stream.total_amount = total_amount;
+        if let Some(end_time) = new_end_time {
+            stream.end_time = end_time;
+        }
+        if stream.end_time <= stream.start_time {
+            return Err(Error::InvalidTimeRange);
+        }
+
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("ToppedUp")),
+            StreamToppedUp {
+                stream_id,
+                sender,
+                amount,
+                total_amount: stream.total_amount,
+                end_time: stream.end_time,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Moves a stream that has not started yet to `new_start_time`. The
+    /// whole schedule moves with it, so durations stay the same.
+    pub fn reschedule(
+        env: Env,
+        stream_id: u64,
+        sender: Address,
+        new_start_time: u64,
+    ) -> Result<(), Error> {
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.sender != sender {
+            return Err(Error::SenderMismatch);
+        }
+        sender.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+        let now = env.ledger().timestamp();
+        if now >= stream.start_time {
+            return Err(Error::StreamStarted);
+        }
+        // pushing the schedule back would let the sender stall an
+        // irrevocable stream indefinitely
+        check_cancelable(&stream, now)?;
+        if new_start_time < now {
+            return Err(Error::InvalidTimeRange);
+        }
+
+        let old_start_time = stream.start_time;
+        move_schedule(&env, &mut stream, new_start_time)?;
+
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Resched")),
+            StreamRescheduled {
+                stream_id,
+                sender,
+                old_start_time,
+                start_time: stream.start_time,
+                end_time: stream.end_time,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Proposes new `total_amount` and `end_time` for a linear stream. Either
+    /// party may propose; the other one has to accept before `expires_at`.
+    /// A sender proposing an increase escrows the difference right away.
+    pub fn propose_amendment(
+        env: Env,
+        stream_id: u64,
+        proposer: Address,
+        total_amount: i128,
+        end_time: u64,
+        expires_at: u64,
+    ) -> Result<(), Error> {
+        require_not_paused(&env)?;
+
+        let stream = read_stream(&env, stream_id)?;
+        if proposer != stream.sender && proposer != stream.recipient {
+            return Err(Error::NotStreamParty);
+        }
+        proposer.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+        if stream.kind != StreamKind::Linear {
+            return Err(Error::UnsupportedStreamKind);
+        }
+        if total_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let now = env.ledger().timestamp();
+        if end_time <= now {
+            return Err(Error::InvalidTimeRange);
+        }
+        if expires_at <= now {
+            return Err(Error::InvalidTimeRange);
+        }
+
+        // a new proposal replaces the pending one
+        let key = DataKey::Amendment(stream_id);
+        if let Some(pending) = read_entry::<Amendment>(&env, &key) {
+            release_amendment_escrow(&env, &stream, &pending);
+        }
+
+        let mut escrowed = 0;
+        if proposer == stream.sender && total_amount > stream.total_amount {
+            escrowed = total_amount - stream.total_amount;
+            escrow(&env, &proposer, &stream.token, escrowed)?;
+        }
+
+        let amendment = Amendment {
+            proposer: proposer.clone(),
+            total_amount,
+            end_time,
+            expires_at,
+            escrowed,
+        };
+        write_entry(&env, &key, &amendment);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("AmendProp")),
+            AmendmentProposed {
+                stream_id,
+                proposer,
+                total_amount,
+                end_time,
+                expires_at,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Accepts the pending amendment. Vesting up to now is settled under the
+    /// old terms, then the rest of the new total streams until the new end.
+    pub fn accept_amendment(env: Env, stream_id: u64, acceptor: Address) -> Result<(), Error> {
+        require_not_paused(&env)?;
+
+        let mut stream = read_stream(&env, stream_id)?;
+        let key = DataKey::Amendment(stream_id);
+        let amendment: Amendment = read_entry(&env, &key).ok_or(Error::NoPendingAmendment)?;
+
+        let counterparty = if amendment.proposer == stream.sender {
+            stream.recipient.clone()
+        } else {
+            stream.sender.clone()
+        };
+        if acceptor != counterparty {
+            return Err(Error::NotCounterparty);
+        }
+        acceptor.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+        let now = env.ledger().timestamp();
+        if now >= amendment.expires_at {
+            return Err(Error::AmendmentExpired);
+        }
+
+        rebase(&mut stream, now);
+        if amendment.total_amount < stream.unlock_amount
+            || amendment.total_amount < stream.claimed_amount
+        {
+            return Err(Error::BelowVestedAmount);
+        }
+        if amendment.end_time <= stream.start_time {
+            return Err(Error::InvalidTimeRange);
+        }
+        if let Some(cliff) = stream.cliff_time {
+            if cliff > amendment.end_time {
+                return Err(Error::InvalidTimeRange);
+            }
+        }
+
+        // settle the difference between the old and new totals with the sender
+        let increase = amendment.total_amount - stream.total_amount;
+        if increase > amendment.escrowed {
+            escrow(
+                &env,
+                &stream.sender,
+                &stream.token,
+                increase - amendment.escrowed,
+            )?;
+        } else if increase < amendment.escrowed {
+            let token_client = TokenClient::new(&env, &stream.token);
+            let contract_address = env.current_contract_address();
+            token_client.transfer(
+                &contract_address,
+                &stream.sender,
+                &(amendment.escrowed - increase),
+            );
+        }
+
+        stream.total_amount = amendment.total_amount;
+        stream.end_time = amendment.end_time;
+
+        env.storage().persistent().remove(&key);
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Amended")),
+            StreamAmended {
+                stream_id,
+                accepted_by: acceptor,
+                total_amount: stream.total_amount,
+                end_time: stream.end_time,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Withdraws or rejects the pending amendment. Either party may call it;
+    /// escrowed funds go back to the sender.
+    pub fn cancel_amendment(env: Env, stream_id: u64, caller: Address) -> Result<(), Error> {
+        let stream = read_stream(&env, stream_id)?;
+        if caller != stream.sender && caller != stream.recipient {
+            return Err(Error::NotStreamParty);
+        }
+        caller.require_auth();
+
+        let key = DataKey::Amendment(stream_id);
+        let amendment: Amendment = read_entry(&env, &key).ok_or(Error::NoPendingAmendment)?;
+
+        release_amendment_escrow(&env, &stream, &amendment);
+        env.storage().persistent().remove(&key);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("AmendCncl")),
+            AmendmentCanceled { stream_id, caller },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_amendment(env: Env, stream_id: u64) -> Option<Amendment> {
+        read_entry(&env, &DataKey::Amendment(stream_id))
+    }
+
+    /// Hands a transferable stream over to a new recipient, together with
+    /// everything vested but not yet claimed.
+    pub fn transfer_recipient(
+        env: Env,
+        stream_id: u64,
+        from: Address,
+        to: Address,
+    ) -> Result<(), Error> {
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.recipient != from {
+            return Err(Error::RecipientMismatch);
+        }
+        from.require_auth();
+
+        if !stream.transferable {
+            return Err(Error::StreamNotTransferable);
+        }
+        if from == to {
+            return Err(Error::SameAddress);
+        }
+
+        // terms proposed by the previous recipient no longer apply
+        let key = DataKey::Amendment(stream_id);
+        if let Some(pending) = read_entry::<Amendment>(&env, &key) {
+            if pending.proposer == from {
+                env.storage().persistent().remove(&key);
+            }
+        }
+
+        stream.recipient = to.clone();
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Transfer")),
+            RecipientTransferred {
+                stream_id,
+                from,
+                to,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// First step of moving the sender role (cancel authority and refund
+    /// destination) to `new_sender`, who must accept it.
+    pub fn propose_sender_transfer(
+        env: Env,
+        stream_id: u64,
+        sender: Address,
+        new_sender: Address,
+    ) -> Result<(), Error> {
+        let stream = read_stream(&env, stream_id)?;
+        if stream.sender != sender {
+            return Err(Error::SenderMismatch);
+        }
+        sender.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+        if new_sender == sender {
+            return Err(Error::SameAddress);
+        }
+
+        write_entry(&env, &DataKey::PendingSender(stream_id), &new_sender);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("SndrProp")),
+            SenderTransferProposed {
+                stream_id,
+                sender,
+                new_sender,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Second step of the sender transfer, signed by the proposed sender.
+    pub fn accept_sender_transfer(
+        env: Env,
+        stream_id: u64,
+        new_sender: Address,
+    ) -> Result<(), Error> {
+        let mut stream = read_stream(&env, stream_id)?;
+        let key = DataKey::PendingSender(stream_id);
+        let pending: Address = read_entry(&env, &key).ok_or(Error::NoPendingSenderTransfer)?;
+        if pending != new_sender {
+            return Err(Error::SenderMismatch);
+        }
+        new_sender.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+
+        let from = stream.sender.clone();
+
+        // terms proposed by the previous sender no longer apply; its escrow
+        // goes back to it before the role moves
+        let amendment_key = DataKey::Amendment(stream_id);
+        if let Some(pending) = read_entry::<Amendment>(&env, &amendment_key) {
+            if pending.proposer == from {
+                release_amendment_escrow(&env, &stream, &pending);
+                env.storage().persistent().remove(&amendment_key);
+            }
+        }
+
+        stream.sender = new_sender.clone();
+        env.storage().persistent().remove(&key);
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("SndrXfer")),
+            SenderTransferred {
+                stream_id,
+                from,
+                to: new_sender,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_pending_sender(env: Env, stream_id: u64) -> Option<Address> {
+        read_entry(&env, &DataKey::PendingSender(stream_id))
+    }
+
+    /// Lets `operator` call `claim` on any of the recipient's streams.
+    pub fn approve_operator(env: Env, recipient: Address, operator: Address) -> Result<(), Error> {
+        recipient.require_auth();
+
+        if operator == recipient {
+            return Err(Error::SameAddress);
+        }
+
+        write_entry(
+            &env,
+            &DataKey::Operator(recipient.clone(), operator.clone()),
+            &true,
+        );
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("OpApprove")),
+            OperatorApproved {
+                recipient,
+                operator,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn revoke_operator(env: Env, recipient: Address, operator: Address) -> Result<(), Error> {
+        recipient.require_auth();
+
+        let key = DataKey::Operator(recipient.clone(), operator.clone());
+        if !env.storage().persistent().has(&key) {
+            return Err(Error::OperatorNotApproved);
+        }
+        env.storage().persistent().remove(&key);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("OpRevoke")),
+            OperatorRevoked {
+                recipient,
+                operator,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn is_operator(env: Env, recipient: Address, operator: Address) -> bool {
+        read_entry(&env, &DataKey::Operator(recipient, operator)).unwrap_or(false)
+    }
+
+    /// Stops vesting until `resume` is called. Already vested funds stay
+    /// claimable while paused.
+    pub fn pause(env: Env, stream_id: u64, sender: Address) -> Result<(), Error> {
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.sender != sender {
+            return Err(Error::SenderMismatch);
+        }
+        sender.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+        if stream.paused_at.is_some() {
+            return Err(Error::StreamPaused);
+        }
+        let now = env.ledger().timestamp();
+        if now >= stream.end_time {
+            return Err(Error::StreamEnded);
+        }
+        check_cancelable(&stream, now)?;
+
+        stream.paused_at = Some(now);
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Paused")),
+            StreamPaused {
+                stream_id,
+                sender,
+                paused_at: now,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Resumes a paused stream. The schedule is shifted by the time spent
+    /// paused, so the stream ends that much later.
+    pub fn resume(env: Env, stream_id: u64, sender: Address) -> Result<(), Error> {
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.sender != sender {
+            return Err(Error::SenderMismatch);
+        }
+        sender.require_auth();
+
+        let paused_at = match stream.paused_at {
+            Some(paused_at) => paused_at,
+            None => return Err(Error::StreamNotPaused),
+        };
+
+        // time paused before start_time did not hold anything back
+        let now = env.ledger().timestamp();
+        let pause_start = if paused_at > stream.start_time {
+            paused_at
+        } else {
+            stream.start_time
+        };
+        if now > pause_start {
+            let new_start_time = stream.start_time + (now - pause_start);
+            move_schedule(&env, &mut stream, new_start_time)?;
+        }
+        stream.paused_at = None;
+
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Resumed")),
+            StreamResumed {
+                stream_id,
+                sender,
+                end_time: stream.end_time,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Adds funds to a rate stream, pushing out the time at which it runs
+    /// dry. If the stream already ran dry, accrual restarts from now.
+    pub fn deposit(env: Env, stream_id: u64, sender: Address, amount: i128) -> Result<(), Error> {
+        require_not_paused(&env)?;
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut stream = read_stream(&env, stream_id)?;
+        if stream.sender != sender {
+            return Err(Error::SenderMismatch);
+        }
+        sender.require_auth();
+
+        if stream.canceled {
+            return Err(Error::StreamCanceled);
+        }
+        let rate_per_second = match stream.kind {
+            StreamKind::Rate(rate) => rate,
+            _ => return Err(Error::UnsupportedStreamKind),
+        };
+
+        let total_amount = stream
+            .total_amount
+            .checked_add(amount)
+            .ok_or(Error::InvalidAmount)?;
+        escrow(&env, &sender, &stream.token, amount)?;
+
+        let now = env.ledger().timestamp();
+        rebase(&mut stream, now);
+        stream.total_amount = total_amount;
+        stream.end_time = depletion_time(
+            stream.start_time,
+            stream.total_amount - stream.unlock_amount,
+            rate_per_second,
+        )?;
+
+        write_stream(&env, stream_id, &stream);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Deposited")),
+            StreamDeposited {
+                stream_id,
+                sender,
+                amount,
+                end_time: stream.end_time,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Sets the admin allowed to upgrade the contract. Can only be called
+    /// once, right after deployment. Until then anyone can call it, so deploy
+    /// and initialize in one invocation, or check `get_admin` before any
+    /// stream is funded.
+    pub fn initialize(env: Env, admin: Address) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
+        }
+        admin.require_auth();
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage()
+            .instance()
+            .set(&DataKey::Version, &CONTRACT_VERSION);
+        extend_instance_ttl(&env);
+
+        Ok(())
+    }
+
+    pub fn get_admin(env: Env) -> Result<Address, Error> {
+        read_admin(&env)
+    }
+
+    pub fn set_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+
+        if new_admin == admin {
+            return Err(Error::SameAddress);
+        }
+
+        env.storage().instance().set(&DataKey::Admin, &new_admin);
+        extend_instance_ttl(&env);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("AdminSet")),
+            AdminChanged {
+                from: admin,
+                to: new_admin,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Replaces the contract code with an already uploaded wasm. Streams and
+    /// escrowed funds stay in place; call `migrate` on the new build if it
+    /// changed the stored data.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Upgraded")),
+            ContractUpgraded {
+                admin,
+                new_wasm_hash: new_wasm_hash.clone(),
+            },
+        );
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash);
+
+        Ok(())
+    }
+
+    /// Circuit breaker. Blocks stream creation, top-ups, deposits and
+    /// amendments, plus claims and/or cancels if requested. Blocking claims
+    /// also covers cancels and renounces that pay out the recipient. Calling
+    /// it again replaces the flags.
+    pub fn pause_contract(env: Env, claims: bool, cancels: bool) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::ContractPause, &ContractPause { claims, cancels });
+        extend_instance_ttl(&env);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("CPaused")),
+            ContractPaused {
+                admin,
+                claims,
+                cancels,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn unpause_contract(env: Env) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+
+        if !env.storage().instance().has(&DataKey::ContractPause) {
+            return Err(Error::NotPaused);
+        }
+        env.storage().instance().remove(&DataKey::ContractPause);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("CUnpaused")),
+            ContractUnpaused { admin },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_contract_pause(env: Env) -> Option<ContractPause> {
+        env.storage().instance().get(&DataKey::ContractPause)
+    }
+
+    /// Sets the default fee rate for new streams and the treasury that
+    /// receives all fees.
+    pub fn set_fee(env: Env, fee_bps: u32, treasury: Address) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+
+        if fee_bps > MAX_FEE_BPS {
+            return Err(Error::FeeTooHigh);
+        }
+
+        env.storage().instance().set(
+            &DataKey::FeeConfig,
+            &FeeConfig {
+                fee_bps,
+                treasury: treasury.clone(),
+            },
+        );
+        extend_instance_ttl(&env);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("FeeSet")),
+            FeeUpdated { fee_bps, treasury },
+        );
+
+        Ok(())
+    }
+
+    /// Overrides the fee rate for new streams in `token`; `None` goes back
+    /// to the default.
+    pub fn set_token_fee(env: Env, token: Address, fee_bps: Option<u32>) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+
+        let key = DataKey::TokenFee(token.clone());
+        match fee_bps {
+            Some(fee_bps) => {
+                if fee_bps > MAX_FEE_BPS {
+                    return Err(Error::FeeTooHigh);
+                }
+                if read_fee_config(&env).is_none() {
+                    return Err(Error::FeeNotConfigured);
+                }
+                env.storage().instance().set(&key, &fee_bps);
+            }
+            None => env.storage().instance().remove(&key),
+        }
+        extend_instance_ttl(&env);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("TokenFee")),
+            TokenFeeUpdated { token, fee_bps },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_fee_config(env: Env) -> Option<FeeConfig> {
+        read_fee_config(&env)
+    }
+
+    /// Fee rate a new stream in `token` would get.
+    pub fn get_fee_bps(env: Env, token: Address) -> u32 {
+        fee_bps_for(&env, &token)
+    }
+
+    pub fn version() -> u32 {
+        CONTRACT_VERSION
+    }
+
+    /// Brings stored data up to this build's version after an upgrade.
+    pub fn migrate(env: Env) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+
+        let from_version: u32 = env.storage().instance().get(&DataKey::Version).unwrap_or(0);
+        if from_version >= CONTRACT_VERSION {
+            return Err(Error::AlreadyMigrated);
+        }
+
+        // Version 1 introduced the admin itself; nothing else to rewrite yet.
+        // Later builds add their steps here, keyed on `from_version`.
+
+        env.storage()
+            .instance()
+            .set(&DataKey::Version, &CONTRACT_VERSION);
+        extend_instance_ttl(&env);
+
+        env.events().publish(
+            (symbol_short!("Stream"), symbol_short!("Migrated")),
+            ContractMigrated {
+                from_version,
+                to_version: CONTRACT_VERSION,
+            },
         );
+
+        Ok(())
+    }
+}
+
+/// Rejects creating streams or adding funds and terms while paused.
+fn require_not_paused(env: &Env) -> Result<(), Error> {
+    if env.storage().instance().has(&DataKey::ContractPause) {
+        return Err(Error::Paused);
     }
+    Ok(())
 }
 
-fn read_stream(env: &Env, stream_id: u64) -> Stream {
+fn require_claims_open(env: &Env) -> Result<(), Error> {
+    match env
+        .storage()
+        .instance()
+        .get::<_, ContractPause>(&DataKey::ContractPause)
+    {
+        Some(pause) if pause.claims => Err(Error::Paused),
+        _ => Ok(()),
+    }
+}
+
+fn require_cancels_open(env: &Env) -> Result<(), Error> {
+    match env
+        .storage()
+        .instance()
+        .get::<_, ContractPause>(&DataKey::ContractPause)
+    {
+        Some(pause) if pause.cancels => Err(Error::Paused),
+        _ => Ok(()),
+    }
+}
+
+fn read_admin(env: &Env) -> Result<Address, Error> {
     env.storage()
+        .instance()
+        .get(&DataKey::Admin)
+        .ok_or(Error::NotInitialized)
+}
+
+/// Escrows `total_amount` from the sender and stores the new stream.
+fn open_stream(env: &Env, stream: Stream) -> Result<u64, Error> {
+    escrow(env, &stream.sender, &stream.token, stream.total_amount)?;
+    Ok(store_new_stream(env, stream))
+}
+
+/// Moves `amount` from the sender into this contract.
+fn escrow(env: &Env, sender: &Address, token: &Address, amount: i128) -> Result<(), Error> {
+    // checks sebder balance.
+    let token_client = TokenClient::new(env, token);
+    let sender_balance = token_client.balance(sender);
+    if sender_balance < amount {
+        return Err(Error::InsufficientBalance);
+    }
+
+    // escrow = transfer total_amount from sender into this contract
+    let contract_address = env.current_contract_address();
+    token_client.transfer(sender, &contract_address, &amount);
+    Ok(())
+}
+
+/// Assigns the next id to an already funded stream and emits `Created`.
+fn store_new_stream(env: &Env, mut stream: Stream) -> u64 {
+    // the fee rate is locked in here so later changes don't affect the stream
+    stream.fee_bps = fee_bps_for(env, &stream.token);
+
+    let mut next_id: u64 = read_entry(env, &DataKey::NextStreamId).unwrap_or(0);
+    next_id += 1;
+
+    write_entry(env, &DataKey::NextStreamId, &next_id);
+    write_stream(env, next_id, &stream);
+
+    env.events().publish(
+        (symbol_short!("Stream"), symbol_short!("Created")),
+        StreamCreated {
+            stream_id: next_id,
+            sender: stream.sender,
+            recipient: stream.recipient,
+            token: stream.token,
+            total_amount: stream.total_amount,
+            start_time: stream.start_time,
+            end_time: stream.end_time,
+            cliff_time: stream.cliff_time,
+            unlock_amount: stream.unlock_amount,
+            fee_bps: stream.fee_bps,
+        },
+    );
+
+    next_id
+}
+
+/// Returns funds escrowed for a proposed increase to the sender.
+fn release_amendment_escrow(env: &Env, stream: &Stream, amendment: &Amendment) {
+    if amendment.escrowed > 0 {
+        let token_client = TokenClient::new(env, &stream.token);
+        let contract_address = env.current_contract_address();
+        token_client.transfer(&contract_address, &stream.sender, &amendment.escrowed);
+    }
+}
+
+/// Discards a pending amendment, returning its escrow to the sender.
+fn drop_amendment(env: &Env, stream_id: u64, stream: &Stream) {
+    let key = DataKey::Amendment(stream_id);
+    if let Some(pending) = read_entry::<Amendment>(env, &key) {
+        release_amendment_escrow(env, stream, &pending);
+        env.storage().persistent().remove(&key);
+    }
+}
+
+/// Cancels a stream at the current ledger time and stores it. With `settle`
+/// the vested but unclaimed balance is marked as claimed. Returns the amounts
+/// owed to the sender and the recipient, and the fee included in the latter;
+/// the caller makes the transfers and emits `Canceled`.
+fn cancel_stream(
+    env: &Env,
+    stream_id: u64,
+    stream: &mut Stream,
+    settle: bool,
+) -> Result<(i128, i128, i128), Error> {
+    let now = env.ledger().timestamp();
+    check_cancelable(stream, now)?;
+
+    // compute vested BEFORE truncating end_time
+    let vested = vested_amount(stream, now);
+    let sender_refund = stream.total_amount - vested;
+    let recipient_amount = if settle {
+        vested - stream.claimed_amount
+    } else {
+        0
+    };
+    if recipient_amount > 0 {
+        require_claims_open(env)?;
+    }
+
+    // a closed stream can't be amended any more
+    drop_amendment(env, stream_id, stream);
+    close_stream(stream, now, vested);
+    let fee = record_claim(stream, recipient_amount);
+
+    write_stream(env, stream_id, stream);
+
+    Ok((sender_refund, recipient_amount, fee))
+}
+
+/// Books `amount` as claimed and flags a canceled stream as settled once
+/// it has paid out in full. Returns the protocol fee included in `amount`.
+fn record_claim(stream: &mut Stream, amount: i128) -> i128 {
+    stream.claimed_amount += amount;
+    // charged on the running total so splitting a claim can't round it away
+    let fee = stream.claimed_amount * stream.fee_bps as i128 / BPS_DENOMINATOR - stream.fees_paid;
+    stream.fees_paid += fee;
+    stream.settled = stream.canceled && stream.claimed_amount == stream.total_amount;
+    fee
+}
+
+/// Sends a protocol fee from escrow to the treasury.
+fn pay_fee(env: &Env, token: &Address, fee: i128) {
+    if fee <= 0 {
+        return;
+    }
+    // a stream only has a fee rate if a treasury was set when it was created
+    if let Some(config) = read_fee_config(env) {
+        TokenClient::new(env, token).transfer(
+            &env.current_contract_address(),
+            &config.treasury,
+            &fee,
+        );
+    }
+}
+
+fn read_fee_config(env: &Env) -> Option<FeeConfig> {
+    env.storage().instance().get(&DataKey::FeeConfig)
+}
+
+/// Fee rate for new streams in `token`: the token override if there is
+/// one, else the default.
+fn fee_bps_for(env: &Env, token: &Address) -> u32 {
+    if let Some(fee_bps) = env
+        .storage()
+        .instance()
+        .get(&DataKey::TokenFee(token.clone()))
+    {
+        return fee_bps;
+    }
+    read_fee_config(env).map_or(0, |config| config.fee_bps)
+}
+
+/// Ends a stream at `now`, leaving the recipient `kept` in total (claimed
+/// or still claimable). Nothing vests past this point.
+fn close_stream(stream: &mut Stream, now: u64, kept: i128) {
+    stream.canceled = true;
+    stream.paused_at = None;
+
+    // truncate end_time so recipient can't claim past cancel point
+    let min_end = if now > stream.start_time {
+        now
+    } else {
+        stream.start_time
+    };
+    if min_end < stream.end_time {
+        stream.end_time = min_end;
+    }
+    // Adjust total_amount to match what the recipient keeps
+    // This ensures that the vested calculation remains correct after truncation
+    stream.total_amount = kept;
+    // the upfront unlock only counts if it had been released by now
+    if stream.unlock_amount > kept {
+        stream.unlock_amount = kept;
+    }
+}
+
+/// Rejects canceling (or pausing) a stream whose cancelability forbids it.
+fn check_cancelable(stream: &Stream, now: u64) -> Result<(), Error> {
+    let cancelable = match stream.cancelability {
+        Cancelability::Always => true,
+        Cancelability::Never => false,
+        Cancelability::After(cancelable_from) => now >= cancelable_from,
+    };
+    if !cancelable {
+        return Err(Error::StreamNotCancelable);
+    }
+    Ok(())
+}
+
+fn read_stream(env: &Env, stream_id: u64) -> Result<Stream, Error> {
+    let stream = env
+        .storage()
         .persistent()
         .get(&DataKey::Stream(stream_id))
-        .unwrap_or_else(|| panic!("stream not found"))
+        .ok_or(Error::StreamNotFound)?;
+    extend_stream_ttl(env, stream_id, &stream);
+    Ok(stream)
 }
 
-fn vested_amount(stream: &Stream, at_time: u64) -> i128 {
-    if at_time <= stream.start_time {
-        return 0;
+fn write_stream(env: &Env, stream_id: u64, stream: &Stream) {
+    env.storage()
+        .persistent()
+        .set(&DataKey::Stream(stream_id), stream);
+    extend_stream_ttl(env, stream_id, stream);
+}
+
+/// Keeps a stream entry (and the contract instance) live until
+/// `STREAM_TTL_GRACE` past its `end_time`, up to the network's maximum TTL.
+fn extend_stream_ttl(env: &Env, stream_id: u64, stream: &Stream) {
+    let remaining = stream.end_time.saturating_sub(env.ledger().timestamp()) / LEDGER_SECONDS;
+    let ledgers = (remaining + STREAM_TTL_GRACE as u64).min(env.storage().max_ttl() as u64) as u32;
+    env.storage()
+        .persistent()
+        .extend_ttl(&DataKey::Stream(stream_id), ledgers, ledgers);
+    extend_instance_ttl(env);
+}
+
+/// Extends the contract instance, and with it the stream id counter, which
+/// every `create_*` call needs even when no stream has been created for a
+/// while.
+fn extend_instance_ttl(env: &Env) {
+    env.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    if env.storage().persistent().has(&DataKey::NextStreamId) {
+        extend_entry_ttl(env, &DataKey::NextStreamId);
     }
+}
 
-    let effective_time = if at_time >= stream.end_time {
-        stream.end_time
-    } else {
-        at_time
+/// Reads a persistent entry other than a stream, extending it if present.
+fn read_entry<V: TryFromVal<Env, Val>>(env: &Env, key: &DataKey) -> Option<V> {
+    let value = env.storage().persistent().get(key);
+    if value.is_some() {
+        extend_entry_ttl(env, key);
+    }
+    value
+}
+
+fn write_entry<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    env.storage().persistent().set(key, value);
+    extend_entry_ttl(env, key);
+}
+
+fn extend_entry_ttl(env: &Env, key: &DataKey) {
+    env.storage()
+        .persistent()
+        .extend_ttl(key, ENTRY_LIFETIME_THRESHOLD, ENTRY_BUMP_AMOUNT);
+}
+
+/// Restarts the schedule at `now`, carrying everything vested so far as the
+/// unlock amount so that vesting up to `now` is preserved.
+fn rebase(stream: &mut Stream, now: u64) {
+    // a paused stream is settled as of the pause
+    let now = match stream.paused_at {
+        Some(paused_at) if paused_at < now => paused_at,
+        _ => now,
+    };
+    if now <= stream.start_time {
+        return;
+    }
+    // before the cliff nothing has vested yet, keep accruing from start_time
+    if let Some(cliff) = stream.cliff_time {
+        if now < cliff {
+            return;
+        }
+    }
+    stream.unlock_amount = vested_amount(stream, now);
+    stream.start_time = now;
+    stream.cliff_time = None;
+}
+
+/// Moves the whole schedule so that it starts at `new_start_time`, keeping
+/// every duration unchanged.
+fn move_schedule(env: &Env, stream: &mut Stream, new_start_time: u64) -> Result<(), Error> {
+    let old_start_time = stream.start_time;
+    let retime = |t: u64| {
+        (t - old_start_time)
+            .checked_add(new_start_time)
+            .ok_or(Error::InvalidTimeRange)
     };
 
-    let elapsed = effective_time - stream.start_time;
-    let total_duration = stream.end_time - stream.start_time;
+    stream.start_time = new_start_time;
+    stream.end_time = retime(stream.end_time)?;
+    stream.cliff_time = stream.cliff_time.map(retime).transpose()?;
+
+    match &stream.kind {
+        StreamKind::Tranched(tranches) => {
+            let mut moved = Vec::new(env);
+            for tranche in tranches.iter() {
+                moved.push_back(Tranche {
+                    timestamp: retime(tranche.timestamp)?,
+                    amount: tranche.amount,
+                });
+            }
+            stream.kind = StreamKind::Tranched(moved);
+        }
+        StreamKind::Segmented(segments) => {
+            let mut moved = Vec::new(env);
+            for segment in segments.iter() {
+                moved.push_back(Segment {
+                    end_time: retime(segment.end_time)?,
+                    amount: segment.amount,
+                });
+            }
+            stream.kind = StreamKind::Segmented(moved);
+        }
+        StreamKind::Linear | StreamKind::Curve(_) | StreamKind::Rate(_) => {}
+    }
+    Ok(())
+}
+
+/// First second at which a rate stream has paid out `amount`.
+fn depletion_time(start_time: u64, amount: i128, rate_per_second: i128) -> Result<u64, Error> {
+    let duration = amount
+        .checked_add(rate_per_second - 1)
+        .ok_or(Error::InvalidAmount)?
+        / rate_per_second;
+    let duration = u64::try_from(duration).map_err(|_| Error::InvalidAmount)?;
+    start_time
+        .checked_add(duration)
+        .ok_or(Error::InvalidTimeRange)
+}
+
+fn vested_amount(stream: &Stream, at_time: u64) -> i128 {
+    // nothing accrues while paused
+    let at_time = match stream.paused_at {
+        Some(paused_at) if paused_at < at_time => paused_at,
+        _ => at_time,
+    };
 
-    if total_duration == 0 {
+    if at_time < stream.start_time {
         return 0;
     }
 
-    stream.total_amount * (elapsed as i128) / (total_duration as i128)
+    // nothing unlocks before the cliff; afterwards the linear accrual since
+    // start_time becomes claimable at once
+    if let Some(cliff) = stream.cliff_time {
+        if at_time < cliff {
+            return 0;
+        }
+    }
+
+    if at_time >= stream.end_time {
+        return stream.total_amount;
+    }
+
+    match &stream.kind {
+        StreamKind::Linear => {
+            // unlock_amount is released at start_time, the remainder streams linearly
+            let elapsed = at_time - stream.start_time;
+            let total_duration = stream.end_time - stream.start_time;
+            let streamed_amount = stream.total_amount - stream.unlock_amount;
+
+            stream.unlock_amount + streamed_amount * (elapsed as i128) / (total_duration as i128)
+        }
+        StreamKind::Tranched(tranches) => {
+            let mut vested: i128 = 0;
+            for tranche in tranches.iter() {
+                if tranche.timestamp > at_time {
+                    break;
+                }
+                vested += tranche.amount;
+            }
+            // a canceled stream keeps only what had unlocked at cancel time
+            vested.min(stream.total_amount)
+        }
+        StreamKind::Segmented(segments) => {
+            let mut vested: i128 = 0;
+            let mut segment_start = stream.start_time;
+            for segment in segments.iter() {
+                if at_time >= segment.end_time {
+                    vested += segment.amount;
+                    segment_start = segment.end_time;
+                    continue;
+                }
+                let elapsed = at_time - segment_start;
+                let duration = segment.end_time - segment_start;
+                vested += segment.amount * (elapsed as i128) / (duration as i128);
+                break;
+            }
+            vested.min(stream.total_amount)
+        }
+        StreamKind::Curve(exponent) => {
+            // end_time is handled above, so the curve always lands exactly
+            // on total_amount and no rounding dust stays in escrow
+            let elapsed = (at_time - stream.start_time) as i128;
+            let total_duration = (stream.end_time - stream.start_time) as i128;
+            let progress = pow_wad(elapsed * WAD / total_duration, *exponent);
+            let streamed_amount = stream.total_amount - stream.unlock_amount;
+
+            stream.unlock_amount + mul_wad(streamed_amount, progress)
+        }
+        StreamKind::Rate(rate_per_second) => {
+            let elapsed = (at_time - stream.start_time) as i128;
+            let streamed = rate_per_second * elapsed;
+
+            (stream.unlock_amount + streamed).min(stream.total_amount)
+        }
+    }
 }
 
 #[cfg(test)]
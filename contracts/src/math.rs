@@ -0,0 +1,107 @@
+//! Deterministic 18-decimal fixed-point helpers used by curve streams.
+//!
+//! Values are `i128` scaled by [`WAD`]. Only integer arithmetic is used, so
+//! results are identical on every validator.
+
+/// Fixed-point one.
+pub const WAD: i128 = 1_000_000_000_000_000_000;
+
+/// ln(2) scaled by [`WAD`].
+const LN_2: i128 = 693_147_180_559_945_309;
+
+/// Below this exponent `exp` is smaller than one unit of [`WAD`].
+const MIN_EXP: i128 = -42 * WAD;
+
+/// Multiplies two fixed-point numbers without overflowing as long as `b`
+/// stays below ~1.7e20 in magnitude.
+pub fn mul_wad(a: i128, b: i128) -> i128 {
+    (a / WAD) * b + (a % WAD) * b / WAD
+}
+
+/// `x ^ y` for `x` in `[0, WAD]` and a positive exponent `y`.
+pub fn pow_wad(x: i128, y: i128) -> i128 {
+    if x <= 0 {
+        return 0;
+    }
+    if x >= WAD {
+        return WAD;
+    }
+
+    // whole exponents are cheaper and exact up to rounding by repeated squaring
+    if y % WAD == 0 {
+        let mut result = WAD;
+        let mut base = x;
+        let mut n = y / WAD;
+        while n > 0 {
+            if n & 1 == 1 {
+                result = mul_wad(result, base);
+            }
+            base = mul_wad(base, base);
+            n >>= 1;
+        }
+        return result;
+    }
+
+    exp_wad(mul_wad(ln_wad(x), y))
+}
+
+/// Natural logarithm of a positive fixed-point number.
+fn ln_wad(x: i128) -> i128 {
+    // normalise x = m * 2^e with m in [1, 2)
+    let mut m = x;
+    let mut e: i128 = 0;
+    while m >= 2 * WAD {
+        m /= 2;
+        e += 1;
+    }
+    while m < WAD {
+        m *= 2;
+        e -= 1;
+    }
+
+    // ln(m) = 2 * atanh(z) = 2 * (z + z^3/3 + z^5/5 + ...), z = (m-1)/(m+1) < 1/3
+    let z = (m - WAD) * WAD / (m + WAD);
+    let z2 = z * z / WAD;
+    let mut term = z;
+    let mut sum: i128 = 0;
+    let mut n: i128 = 1;
+    while term != 0 {
+        sum += term / n;
+        term = term * z2 / WAD;
+        n += 2;
+    }
+
+    e * LN_2 + 2 * sum
+}
+
+/// Exponential of a non-positive fixed-point number.
+fn exp_wad(x: i128) -> i128 {
+    if x < MIN_EXP {
+        return 0;
+    }
+    if x >= 0 {
+        return WAD;
+    }
+
+    // x = k * ln(2) + r with r in [0, ln(2)), so exp(x) = 2^k * exp(r)
+    let mut k = x / LN_2;
+    let mut r = x - k * LN_2;
+    if r < 0 {
+        k -= 1;
+        r += LN_2;
+    }
+
+    let mut term = WAD;
+    let mut sum = WAD;
+    let mut n: i128 = 1;
+    loop {
+        term = term * r / WAD / n;
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        n += 1;
+    }
+
+    sum >> (-k)
+}
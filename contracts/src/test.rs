@@ -2,8 +2,11 @@
 extern crate std;
 use super::*;
 use soroban_sdk::{
-    testutils::{Address as _, Events, Ledger},
-    token, Address, Env, IntoVal,
+    testutils::{
+        storage::Instance as _, storage::Persistent as _, Address as _, AuthorizedFunction,
+        AuthorizedInvocation, Events, Ledger,
+    },
+    token, vec, Address, Bytes, Env, IntoVal, Symbol,
 };
 
 fn create_token(env: &Env, admin: &Address) -> Address {
@@ -24,9 +27,31 @@ fn test_get_next_stream_id() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &5000);
-    client.create_stream(&sender, &recipient, &token, &1000, &1000, &2000);
+    client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &1000,
+        &2000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     assert_eq!(client.get_next_stream_id(), 1);
-    client.create_stream(&sender, &recipient, &token, &1000, &1000, &2000);
+    client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &1000,
+        &2000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     assert_eq!(client.get_next_stream_id(), 2);
 }
 
@@ -42,7 +67,18 @@ fn test_claim_transfers_tokens_to_recipient() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 500);
     let claimed = client.claim(&stream_id, &recipient, &500);
     assert_eq!(claimed, 500);
@@ -62,7 +98,18 @@ fn test_claim_partial_then_full() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 500);
     client.claim(&stream_id, &recipient, &300);
     env.ledger().with_mut(|l| l.timestamp = 1000);
@@ -72,7 +119,6 @@ fn test_claim_partial_then_full() {
 }
 
 #[test]
-#[should_panic(expected = "amount exceeds claimable")]
 fn test_claim_cannot_exceed_vested_amount() {
     let env = Env::default();
     env.mock_all_auths();
@@ -84,13 +130,24 @@ fn test_claim_cannot_exceed_vested_amount() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 250);
-    client.claim(&stream_id, &recipient, &500);
+    let result = client.try_claim(&stream_id, &recipient, &500);
+    assert_eq!(result, Err(Ok(Error::AmountExceedsClaimable)));
 }
 
 #[test]
-#[should_panic(expected = "amount exceeds claimable")]
 fn test_claim_cannot_double_claim() {
     let env = Env::default();
     env.mock_all_auths();
@@ -102,14 +159,25 @@ fn test_claim_cannot_double_claim() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 500);
     client.claim(&stream_id, &recipient, &500);
-    client.claim(&stream_id, &recipient, &500);
+    let result = client.try_claim(&stream_id, &recipient, &500);
+    assert_eq!(result, Err(Ok(Error::AmountExceedsClaimable)));
 }
 
 #[test]
-#[should_panic(expected = "recipient mismatch")]
 fn test_claim_fails_with_wrong_recipient() {
     let env = Env::default();
     env.mock_all_auths();
@@ -122,13 +190,24 @@ fn test_claim_fails_with_wrong_recipient() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 500);
-    client.claim(&stream_id, &wrong_recipient, &500);
+    let result = client.try_claim(&stream_id, &wrong_recipient, &500);
+    assert_eq!(result, Err(Ok(Error::RecipientMismatch)));
 }
 
 #[test]
-#[should_panic(expected = "insufficient sender balance")]
 fn test_create_stream_fails_with_insufficient_sender_balance() {
     let env = Env::default();
     env.mock_all_auths();
@@ -140,7 +219,19 @@ fn test_create_stream_fails_with_insufficient_sender_balance() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &100);
-    client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let result = client.try_create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
 }
 
 #[test]
@@ -155,7 +246,18 @@ fn test_claimable_before_stream_start_returns_zero() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &1000, &2000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &1000,
+        &2000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     assert_eq!(client.claimable(&stream_id, &999), 0);
     assert_eq!(client.claimable(&stream_id, &1000), 0);
 }
@@ -172,7 +274,18 @@ fn test_claimable_during_stream_is_linear() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     assert_eq!(client.claimable(&stream_id, &250), 250);
     assert_eq!(client.claimable(&stream_id, &500), 500);
     assert_eq!(client.claimable(&stream_id, &750), 750);
@@ -190,7 +303,18 @@ fn test_claimable_accounts_for_already_claimed() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 500);
     client.claim(&stream_id, &recipient, &300);
     assert_eq!(client.claimable(&stream_id, &500), 200);
@@ -208,7 +332,18 @@ fn test_claimable_after_stream_end_caps_at_total() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     assert_eq!(client.claimable(&stream_id, &1000), 1000);
     assert_eq!(client.claimable(&stream_id, &9999), 1000);
 }
@@ -225,9 +360,20 @@ fn test_cancel_refunds_unclaimed_to_sender() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 500);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
     let token_client = token::Client::new(&env, &token);
     assert_eq!(token_client.balance(&sender), 500);
 }
@@ -244,8 +390,19 @@ fn test_cancel_marks_stream_as_canceled() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
-    client.cancel(&stream_id, &sender);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.cancel(&stream_id, &sender, &false);
     let stream = client.get_stream(&stream_id);
     assert!(stream.canceled);
 }
@@ -262,9 +419,20 @@ fn test_cancel_idempotent_double_cancel_does_not_panic() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
-    client.cancel(&stream_id, &sender);
-    client.cancel(&stream_id, &sender);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.cancel(&stream_id, &sender, &false);
+    client.cancel(&stream_id, &sender, &false);
 }
 
 #[test]
@@ -279,16 +447,26 @@ fn test_cancel_recipient_cannot_claim_beyond_vested_at_cancel_time() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 500);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
     client.claim(&stream_id, &recipient, &500);
     let token_client = token::Client::new(&env, &token);
     assert_eq!(token_client.balance(&recipient), 500);
 }
 
 #[test]
-#[should_panic(expected = "sender mismatch")]
 fn test_cancel_fails_with_wrong_sender() {
     let env = Env::default();
     env.mock_all_auths();
@@ -301,13 +479,24 @@ fn test_cancel_fails_with_wrong_sender() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
-    client.cancel(&stream_id, &wrong_sender);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_cancel(&stream_id, &wrong_sender, &false);
+    assert_eq!(result, Err(Ok(Error::SenderMismatch)));
 }
 
 #[test]
-#[should_panic(expected = "amount must be positive")]
-fn test_claim_zero_amount_panics() {
+fn test_claim_zero_amount_fails() {
     let env = Env::default();
     env.mock_all_auths();
     let contract_id = env.register_contract(None, StellarStreamContract);
@@ -318,14 +507,25 @@ fn test_claim_zero_amount_panics() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 500);
-    client.claim(&stream_id, &recipient, &0);
+    let result = client.try_claim(&stream_id, &recipient, &0);
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
 }
 
 #[test]
-#[should_panic(expected = "amount exceeds claimable")]
-fn test_claim_before_stream_start_panics() {
+fn test_claim_before_stream_start_fails() {
     let env = Env::default();
     env.mock_all_auths();
     let contract_id = env.register_contract(None, StellarStreamContract);
@@ -336,24 +536,35 @@ fn test_claim_before_stream_start_panics() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &1000, &2000);
-    client.claim(&stream_id, &recipient, &1);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &1000,
+        &2000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_claim(&stream_id, &recipient, &1);
+    assert_eq!(result, Err(Ok(Error::AmountExceedsClaimable)));
 }
 
 #[test]
-#[should_panic(expected = "stream not found")]
-fn test_claim_nonexistent_stream_panics() {
+fn test_claim_nonexistent_stream_fails() {
     let env = Env::default();
     env.mock_all_auths();
     let contract_id = env.register_contract(None, StellarStreamContract);
     let client = StellarStreamContractClient::new(&env, &contract_id);
     let recipient = Address::generate(&env);
-    client.claim(&999, &recipient, &100);
+    let result = client.try_claim(&999, &recipient, &100);
+    assert_eq!(result, Err(Ok(Error::StreamNotFound)));
 }
 
 #[test]
-#[should_panic(expected = "total_amount must be positive")]
-fn test_create_stream_zero_amount_panics() {
+fn test_create_stream_zero_amount_fails() {
     let env = Env::default();
     env.mock_all_auths();
     let contract_id = env.register_contract(None, StellarStreamContract);
@@ -362,12 +573,23 @@ fn test_create_stream_zero_amount_panics() {
     let sender = Address::generate(&env);
     let recipient = Address::generate(&env);
     let token = create_token(&env, &admin);
-    client.create_stream(&sender, &recipient, &token, &0, &0, &1000);
+    let result = client.try_create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &0,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
 }
 
 #[test]
-#[should_panic(expected = "end_time must be greater than start_time")]
-fn test_create_stream_invalid_time_range_panics() {
+fn test_create_stream_invalid_time_range_fails() {
     let env = Env::default();
     env.mock_all_auths();
     let contract_id = env.register_contract(None, StellarStreamContract);
@@ -378,7 +600,19 @@ fn test_create_stream_invalid_time_range_panics() {
     let token = create_token(&env, &admin);
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
-    client.create_stream(&sender, &recipient, &token, &1000, &1000, &1000);
+    let result = client.try_create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &1000,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
 }
 
 #[test]
@@ -397,7 +631,18 @@ fn test_event_emissions() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     let last_event = env.events().all().last().unwrap();
 
     assert_eq!(last_event.0, contract_id);
@@ -417,6 +662,9 @@ fn test_event_emissions() {
             total_amount: 1000,
             start_time: 0,
             end_time: 1000,
+            cliff_time: None,
+            unlock_amount: 0,
+            fee_bps: 0,
         }
     );
 
@@ -437,10 +685,11 @@ fn test_event_emissions() {
             stream_id,
             recipient: recipient.clone(),
             amount: 500,
+            fee: 0,
         }
     );
 
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let last_event = env.events().all().last().unwrap();
     assert_eq!(last_event.0, contract_id);
@@ -455,12 +704,13 @@ fn test_event_emissions() {
         StreamCanceled {
             stream_id,
             sender: sender.clone(),
+            sender_refund: 500,
+            recipient_amount: 0,
+            fee: 0,
         }
     );
 }
 
-
-
 // -----------------------------------------------------------------
 // CANCEL BEFORE STREAM START
 // -----------------------------------------------------------------
@@ -482,11 +732,22 @@ fn test_cancel_before_start_refunds_full_amount_to_sender() {
     token_admin.mint(&sender, &1000);
 
     // Stream starts at t=500, current ledger time is 0 (before start)
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &500, &1500);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &500,
+        &1500,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     // Sender balance is 0 after escrow; cancel at t=0 (before start_time=500)
     env.ledger().with_mut(|l| l.timestamp = 0);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let token_client = token::Client::new(&env, &token);
     // Full amount must come back to sender
@@ -510,20 +771,30 @@ fn test_cancel_before_start_recipient_claimable_is_zero() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &500, &1500);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &500,
+        &1500,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 0);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     // claimable at any future time must be 0 because end_time was truncated
     assert_eq!(client.claimable(&stream_id, &1500), 0);
     assert_eq!(client.claimable(&stream_id, &9999), 0);
 }
 
-/// Attempting to claim any amount after a pre start cancel must panic.
+/// Attempting to claim any amount after a pre start cancel must fail.
 #[test]
-#[should_panic(expected = "amount exceeds claimable")]
-fn test_cancel_before_start_claim_attempt_panics() {
+fn test_cancel_before_start_claim_attempt_fails() {
     let env = Env::default();
     env.mock_all_auths();
     let contract_id = env.register_contract(None, StellarStreamContract);
@@ -536,14 +807,26 @@ fn test_cancel_before_start_claim_attempt_panics() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &500, &1500);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &500,
+        &1500,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 0);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     // Advance time well past original end — should still be unclaim­able
     env.ledger().with_mut(|l| l.timestamp = 2000);
-    client.claim(&stream_id, &recipient, &1);
+    let result = client.try_claim(&stream_id, &recipient, &1);
+    assert_eq!(result, Err(Ok(Error::AmountExceedsClaimable)));
 }
 
 // -----------------------------------------------------------------
@@ -567,10 +850,21 @@ fn test_cancel_at_quarter_vesting() {
     token_admin.mint(&sender, &1000);
 
     // Stream: t=0..1000, total=1000 → 1 token er second
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 250); // 25 % through
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let token_client = token::Client::new(&env, &token);
     // Sender must receive the unvested 75 %
@@ -597,10 +891,21 @@ fn test_cancel_midstream_no_prior_claims_splits_correctly() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 500);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let token_client = token::Client::new(&env, &token);
     assert_eq!(token_client.balance(&sender), 500);
@@ -633,10 +938,21 @@ fn test_cancel_midstream_non_round_amounts() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1200);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1200, &0, &1200);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1200,
+        &0,
+        &1200,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 900); // 75 %
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let token_client = token::Client::new(&env, &token);
     assert_eq!(token_client.balance(&sender), 300);
@@ -662,7 +978,18 @@ fn test_cancel_midstream_after_partial_claim_correct_refund() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     // Recipient claims 200 at t=400 (vested=400 at that point)
     env.ledger().with_mut(|l| l.timestamp = 400);
@@ -670,7 +997,7 @@ fn test_cancel_midstream_after_partial_claim_correct_refund() {
 
     // Sender cancels at t=600 (vested=600)
     env.ledger().with_mut(|l| l.timestamp = 600);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let token_client = token::Client::new(&env, &token);
     // Sender gets back 1000 - 600 = 400
@@ -691,7 +1018,6 @@ fn test_cancel_midstream_after_partial_claim_correct_refund() {
 /// After a mid-stream cancel the recipient cannot claim more than what
 /// was vested at cancel time, even if ledger time advances further.
 #[test]
-#[should_panic(expected = "amount exceeds claimable")]
 fn test_cancel_midstream_recipient_cannot_claim_past_cancel_point() {
     let env = Env::default();
     env.mock_all_auths();
@@ -705,14 +1031,26 @@ fn test_cancel_midstream_recipient_cannot_claim_past_cancel_point() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 500);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     // Try to claim 501  one more than vested at cancel time
     env.ledger().with_mut(|l| l.timestamp = 2000); // far future
-    client.claim(&stream_id, &recipient, &501);
+    let result = client.try_claim(&stream_id, &recipient, &501);
+    assert_eq!(result, Err(Ok(Error::AmountExceedsClaimable)));
 }
 
 // -----------------------------------------------------------------
@@ -734,10 +1072,21 @@ fn test_cancel_at_end_time_sender_gets_zero_refund() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 1000); // exactly at end
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let token_client = token::Client::new(&env, &token);
     // Sender transferred 1000 into escrow and should get 0 back
@@ -759,10 +1108,21 @@ fn test_cancel_after_end_time_sender_gets_zero_refund() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 9999); // long after end
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let token_client = token::Client::new(&env, &token);
     assert_eq!(token_client.balance(&sender), 0);
@@ -784,10 +1144,21 @@ fn test_cancel_after_full_vesting_recipient_can_claim_all() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     env.ledger().with_mut(|l| l.timestamp = 9999);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     // Recipient should be able to claim the full amount
     assert_eq!(client.claimable(&stream_id, &9999), 1000);
@@ -813,7 +1184,18 @@ fn test_cancel_after_full_vesting_with_partial_prior_claim() {
     let token_admin = token::StellarAssetClient::new(&env, &token);
     token_admin.mint(&sender, &1000);
 
-    let stream_id = client.create_stream(&sender, &recipient, &token, &1000, &0, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
 
     // recipient claims 300 before stream ends
     env.ledger().with_mut(|l| l.timestamp = 500);
@@ -821,7 +1203,7 @@ fn test_cancel_after_full_vesting_with_partial_prior_claim() {
 
     // Sender cancels after full vesting
     env.ledger().with_mut(|l| l.timestamp = 1500);
-    client.cancel(&stream_id, &sender);
+    client.cancel(&stream_id, &sender, &false);
 
     let token_client = token::Client::new(&env, &token);
     assert_eq!(token_client.balance(&sender), 0);
@@ -857,11 +1239,22 @@ fn test_cancel_token_conservation_across_timings() {
     let token_client = token::Client::new(&env, &token);
 
     // --- Stream A: cancel before start ---
-    let a = client.create_stream(&sender, &recipient, &token, &1200, &500, &1700);
+    let a = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1200,
+        &500,
+        &1700,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 100); // before start_time=500
     let sender_before_a = token_client.balance(&sender);
     let recipient_before_a = token_client.balance(&recipient);
-    client.cancel(&a, &sender);
+    client.cancel(&a, &sender, &false);
     // Full refund to sender; recipient gets nothing
     assert_eq!(
         token_client.balance(&sender) + token_client.balance(&recipient)
@@ -872,11 +1265,22 @@ fn test_cancel_token_conservation_across_timings() {
 
     // --- Stream B: cancel at 50 % ---
     env.ledger().with_mut(|l| l.timestamp = 0);
-    let b = client.create_stream(&sender, &recipient, &token, &1200, &0, &1200);
+    let b = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1200,
+        &0,
+        &1200,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 600); // 50 %
     let sender_before_b = token_client.balance(&sender);
     let recipient_before_b = token_client.balance(&recipient);
-    client.cancel(&b, &sender);
+    client.cancel(&b, &sender, &false);
     client.claim(&b, &recipient, &600);
     assert_eq!(
         token_client.balance(&sender) + token_client.balance(&recipient)
@@ -887,11 +1291,22 @@ fn test_cancel_token_conservation_across_timings() {
 
     // --- Stream C: cancel after full vesting ---
     env.ledger().with_mut(|l| l.timestamp = 0);
-    let c = client.create_stream(&sender, &recipient, &token, &1200, &0, &1200);
+    let c = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1200,
+        &0,
+        &1200,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
     env.ledger().with_mut(|l| l.timestamp = 9999); // after end
     let sender_before_c = token_client.balance(&sender);
     let recipient_before_c = token_client.balance(&recipient);
-    client.cancel(&c, &sender);
+    client.cancel(&c, &sender, &false);
     client.claim(&c, &recipient, &1200);
     assert_eq!(
         token_client.balance(&sender) + token_client.balance(&recipient)
@@ -899,4 +1314,6167 @@ fn test_cancel_token_conservation_across_timings() {
             - recipient_before_c,
         1200
     );
-}
\ No newline at end of file
+}
+// -----------------------------------------------------------------
+// CLIFF VESTING
+// -----------------------------------------------------------------
+
+/// Nothing is claimable before the cliff; at the cliff the linear amount
+/// accrued since start_time unlocks at once.
+#[test]
+fn test_cliff_unlocks_accrued_amount_at_cliff() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    // Stream: t=0..1000 with a cliff at t=400
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &Some(400),
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.claimable(&stream_id, &100), 0);
+    assert_eq!(client.claimable(&stream_id, &399), 0);
+    assert_eq!(client.claimable(&stream_id, &400), 400);
+    assert_eq!(client.claimable(&stream_id, &700), 700);
+    assert_eq!(client.claimable(&stream_id, &1000), 1000);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.cliff_time, Some(400));
+}
+
+/// Claiming before the cliff must be rejected even though time has passed
+/// since start_time.
+#[test]
+fn test_cliff_claim_before_cliff_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &Some(400),
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    let result = client.try_claim(&stream_id, &recipient, &1);
+    assert_eq!(result, Err(Ok(Error::AmountExceedsClaimable)));
+}
+
+/// Cancel before the cliff refunds the full amount to the sender and leaves
+/// nothing for the recipient, even after the original cliff passes.
+#[test]
+fn test_cliff_cancel_before_cliff_refunds_full_amount() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &Some(400),
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 1000);
+    assert_eq!(client.claimable(&stream_id, &400), 0);
+    assert_eq!(client.claimable(&stream_id, &9999), 0);
+}
+
+/// Cancel after the cliff splits like a regular linear stream.
+#[test]
+fn test_cliff_cancel_after_cliff_splits_linearly() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &Some(400),
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 600);
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 400);
+    client.claim(&stream_id, &recipient, &600);
+    assert_eq!(token_client.balance(&recipient), 600);
+}
+
+#[test]
+fn test_create_stream_cliff_outside_range_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let result = client.try_create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1000,
+        &Some(50),
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
+}
+
+// -----------------------------------------------------------------
+// INITIAL UNLOCK
+// -----------------------------------------------------------------
+
+/// unlock_amount is released at start_time and the rest streams linearly.
+/// total=1000, unlock=200, t=0..800 → 1 token per second after the unlock.
+#[test]
+fn test_unlock_amount_released_at_start() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &900,
+        &None,
+        &200,
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.claimable(&stream_id, &99), 0);
+    assert_eq!(client.claimable(&stream_id, &100), 200);
+    assert_eq!(client.claimable(&stream_id, &500), 600);
+    assert_eq!(client.claimable(&stream_id, &900), 1000);
+
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    client.claim(&stream_id, &recipient, &200);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 200);
+}
+
+/// With a cliff, the unlock is held back until the cliff together with the
+/// linear accrual.
+#[test]
+fn test_unlock_amount_waits_for_cliff() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &800,
+        &Some(400),
+        &200,
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.claimable(&stream_id, &0), 0);
+    assert_eq!(client.claimable(&stream_id, &399), 0);
+    assert_eq!(client.claimable(&stream_id, &400), 600);
+}
+
+/// Cancel mid-stream: the unlock counts as vested, so the sender is only
+/// refunded the unstreamed part of the remainder.
+/// total=1000, unlock=200, t=0..800, cancel at t=400 → vested=600.
+#[test]
+fn test_unlock_amount_cancel_midstream_refund_split() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &800,
+        &None,
+        &200,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 400);
+    assert_eq!(client.claimable(&stream_id, &9999), 600);
+    client.claim(&stream_id, &recipient, &600);
+    assert_eq!(token_client.balance(&recipient), 600);
+}
+
+/// Cancel before start refunds everything, including the unlock.
+#[test]
+fn test_unlock_amount_cancel_before_start_refunds_full_amount() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &500,
+        &1500,
+        &None,
+        &300,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 1000);
+    assert_eq!(client.claimable(&stream_id, &500), 0);
+    assert_eq!(client.claimable(&stream_id, &9999), 0);
+}
+
+#[test]
+fn test_create_stream_unlock_exceeding_total_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let result = client.try_create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &1001,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+}
+
+// -----------------------------------------------------------------
+// TRANCHED STREAMS
+// -----------------------------------------------------------------
+
+/// Three tranches of 300/300/400 unlocking at t=100, 200 and 300.
+/// Nothing vests between steps.
+#[test]
+fn test_tranched_claimable_follows_steps() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: 300,
+        },
+        Tranche {
+            timestamp: 200,
+            amount: 300,
+        },
+        Tranche {
+            timestamp: 300,
+            amount: 400,
+        },
+    ];
+    let stream_id = client.create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.claimable(&stream_id, &99), 0);
+    assert_eq!(client.claimable(&stream_id, &100), 300);
+    assert_eq!(client.claimable(&stream_id, &199), 300);
+    assert_eq!(client.claimable(&stream_id, &200), 600);
+    assert_eq!(client.claimable(&stream_id, &300), 1000);
+    assert_eq!(client.claimable(&stream_id, &9999), 1000);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.end_time, 300);
+    assert_eq!(stream.kind, StreamKind::Tranched(tranches));
+}
+
+#[test]
+fn test_tranched_claim_each_step() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: 500,
+        },
+        Tranche {
+            timestamp: 200,
+            amount: 500,
+        },
+    ];
+    let stream_id = client.create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 150);
+    client.claim(&stream_id, &recipient, &500);
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    client.claim(&stream_id, &recipient, &500);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 1000);
+}
+
+/// Claiming between steps cannot reach into the next tranche.
+#[test]
+fn test_tranched_claim_before_next_step_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: 500,
+        },
+        Tranche {
+            timestamp: 200,
+            amount: 500,
+        },
+    ];
+    let stream_id = client.create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 199);
+    let result = client.try_claim(&stream_id, &recipient, &501);
+    assert_eq!(result, Err(Ok(Error::AmountExceedsClaimable)));
+}
+
+/// Cancel between steps refunds every tranche that has not unlocked yet.
+#[test]
+fn test_tranched_cancel_refunds_locked_tranches() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: 300,
+        },
+        Tranche {
+            timestamp: 200,
+            amount: 300,
+        },
+        Tranche {
+            timestamp: 300,
+            amount: 400,
+        },
+    ];
+    let stream_id = client.create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 250);
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 400);
+    assert_eq!(client.claimable(&stream_id, &9999), 600);
+    client.claim(&stream_id, &recipient, &600);
+    assert_eq!(token_client.balance(&recipient), 600);
+}
+
+#[test]
+fn test_create_tranched_stream_sum_mismatch_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: 300,
+        },
+        Tranche {
+            timestamp: 200,
+            amount: 300,
+        },
+    ];
+    let result = client.try_create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidSchedule)));
+}
+
+#[test]
+fn test_create_tranched_stream_unordered_timestamps_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 200,
+            amount: 500,
+        },
+        Tranche {
+            timestamp: 200,
+            amount: 500,
+        },
+    ];
+    let result = client.try_create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
+}
+
+#[test]
+fn test_create_tranched_stream_amount_overflow_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: i128::MAX,
+        },
+        Tranche {
+            timestamp: 200,
+            amount: 1,
+        },
+    ];
+    let result = client.try_create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+}
+
+// -----------------------------------------------------------------
+// SEGMENTED STREAMS
+// -----------------------------------------------------------------
+
+/// Back-loaded schedule: 100 over t=0..1000, then 900 over t=1000..2000.
+#[test]
+fn test_segmented_claimable_walks_segments() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let segments = vec![
+        &env,
+        Segment {
+            end_time: 1000,
+            amount: 100,
+        },
+        Segment {
+            end_time: 2000,
+            amount: 900,
+        },
+    ];
+    let stream_id = client.create_segmented_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &segments,
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.claimable(&stream_id, &0), 0);
+    assert_eq!(client.claimable(&stream_id, &500), 50);
+    assert_eq!(client.claimable(&stream_id, &1000), 100);
+    assert_eq!(client.claimable(&stream_id, &1500), 550);
+    assert_eq!(client.claimable(&stream_id, &2000), 1000);
+    assert_eq!(client.claimable(&stream_id, &9999), 1000);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.start_time, 0);
+    assert_eq!(stream.end_time, 2000);
+}
+
+/// A zero-amount segment acts as a gap where nothing accrues.
+#[test]
+fn test_segmented_zero_amount_segment_pauses_vesting() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let segments = vec![
+        &env,
+        Segment {
+            end_time: 200,
+            amount: 400,
+        },
+        Segment {
+            end_time: 400,
+            amount: 0,
+        },
+        Segment {
+            end_time: 600,
+            amount: 600,
+        },
+    ];
+    let stream_id = client.create_segmented_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &segments,
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.claimable(&stream_id, &150), 200);
+    assert_eq!(client.claimable(&stream_id, &200), 400);
+    assert_eq!(client.claimable(&stream_id, &399), 400);
+    assert_eq!(client.claimable(&stream_id, &500), 700);
+}
+
+/// Cancel in the middle of the second segment.
+/// vested at t=1500 = 100 + 450 = 550, sender refund = 450.
+#[test]
+fn test_segmented_cancel_midstream_refund_split() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let segments = vec![
+        &env,
+        Segment {
+            end_time: 1000,
+            amount: 100,
+        },
+        Segment {
+            end_time: 2000,
+            amount: 900,
+        },
+    ];
+    let stream_id = client.create_segmented_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &segments,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 1500);
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 450);
+    assert_eq!(client.claimable(&stream_id, &9999), 550);
+    client.claim(&stream_id, &recipient, &550);
+    assert_eq!(token_client.balance(&recipient), 550);
+}
+
+#[test]
+fn test_create_segmented_stream_unordered_segments_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let segments = vec![
+        &env,
+        Segment {
+            end_time: 1000,
+            amount: 500,
+        },
+        Segment {
+            end_time: 500,
+            amount: 500,
+        },
+    ];
+    let result = client.try_create_segmented_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &segments,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
+}
+
+#[test]
+fn test_create_segmented_stream_sum_mismatch_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let segments = vec![
+        &env,
+        Segment {
+            end_time: 1000,
+            amount: 999,
+        },
+    ];
+    let result = client.try_create_segmented_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &segments,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidSchedule)));
+}
+
+#[test]
+fn test_create_segmented_stream_amount_overflow_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let segments = vec![
+        &env,
+        Segment {
+            end_time: 500,
+            amount: i128::MAX,
+        },
+        Segment {
+            end_time: 1000,
+            amount: 1,
+        },
+    ];
+    let result = client.try_create_segmented_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &segments,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+}
+
+// -----------------------------------------------------------------
+// CURVE STREAMS
+// -----------------------------------------------------------------
+
+/// Quadratic curve (exponent 2.0) back-loads vesting.
+#[test]
+fn test_curve_quadratic_back_loads_vesting() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1_000_000);
+
+    let stream_id = client.create_curve_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1_000_000,
+        &0,
+        &1000,
+        &(2 * WAD),
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.claimable(&stream_id, &0), 0);
+    assert_eq!(client.claimable(&stream_id, &250), 62_500);
+    assert_eq!(client.claimable(&stream_id, &500), 250_000);
+    assert_eq!(client.claimable(&stream_id, &750), 562_500);
+    assert_eq!(client.claimable(&stream_id, &1000), 1_000_000);
+    assert_eq!(
+        client.get_stream(&stream_id).kind,
+        StreamKind::Curve(2 * WAD)
+    );
+}
+
+/// Square-root curve (exponent 0.5) front-loads vesting and is computed
+/// with the fractional fixed-point path.
+#[test]
+fn test_curve_fractional_exponent_front_loads_vesting() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1_000_000);
+
+    let stream_id = client.create_curve_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1_000_000,
+        &0,
+        &1000,
+        &(WAD / 2),
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.claimable(&stream_id, &250), 500_000);
+    assert_eq!(client.claimable(&stream_id, &500), 707_106);
+
+    // vesting never goes backwards along the curve
+    let mut previous = 0;
+    for t in (0..=1000u64).step_by(10) {
+        let vested = client.claimable(&stream_id, &t);
+        assert!(vested >= previous);
+        previous = vested;
+    }
+}
+
+/// An awkward total and exponent still release exactly total_amount at
+/// end_time, leaving no dust in escrow.
+#[test]
+fn test_curve_reaches_total_exactly_at_end() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &999_999_997);
+
+    let stream_id = client.create_curve_stream(
+        &sender,
+        &recipient,
+        &token,
+        &999_999_997,
+        &0,
+        &777,
+        &(3 * WAD / 2),
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert!(client.claimable(&stream_id, &776) < 999_999_997);
+    assert_eq!(client.claimable(&stream_id, &777), 999_999_997);
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    let first = client.claimable(&stream_id, &400);
+    client.claim(&stream_id, &recipient, &first);
+    env.ledger().with_mut(|l| l.timestamp = 777);
+    client.claim(&stream_id, &recipient, &(999_999_997 - first));
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 999_999_997);
+    assert_eq!(token_client.balance(&contract_id), 0);
+}
+
+/// Cancel on a curve refunds what has not vested along the curve.
+#[test]
+fn test_curve_cancel_midstream_refund_split() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1_000_000);
+
+    let stream_id = client.create_curve_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1_000_000,
+        &0,
+        &1000,
+        &(2 * WAD),
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 750_000);
+    assert_eq!(client.claimable(&stream_id, &9999), 250_000);
+}
+
+#[test]
+fn test_create_curve_stream_zero_exponent_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let result = client.try_create_curve_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidSchedule)));
+}
+
+// -----------------------------------------------------------------
+// RATE STREAMS
+// -----------------------------------------------------------------
+
+/// 10 tokens per second with a 1000 deposit runs dry at t=100 and stops
+/// accruing afterwards.
+#[test]
+fn test_rate_stream_stops_when_deposit_exhausted() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &10,
+        &0,
+        &1000,
+        &false,
+        &Cancelability::Always,
+    );
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.end_time, 100);
+    assert_eq!(stream.kind, StreamKind::Rate(10));
+    assert_eq!(client.claimable(&stream_id, &50), 500);
+    assert_eq!(client.claimable(&stream_id, &100), 1000);
+    assert_eq!(client.claimable(&stream_id, &500), 1000);
+}
+
+/// A deposit that does not divide evenly by the rate ends on the second
+/// that pays out the remainder.
+#[test]
+fn test_rate_stream_uneven_deposit_rounds_end_time_up() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &10);
+
+    let stream_id = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &3,
+        &0,
+        &10,
+        &false,
+        &Cancelability::Always,
+    );
+
+    assert_eq!(client.get_stream(&stream_id).end_time, 4);
+    assert_eq!(client.claimable(&stream_id, &3), 9);
+    assert_eq!(client.claimable(&stream_id, &4), 10);
+}
+
+/// A deposit that would take longer than a u64 of seconds to pay out, or
+/// end past the largest timestamp, is rejected instead of wrapping.
+#[test]
+fn test_rate_stream_out_of_range_end_time_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &i128::MAX);
+
+    let result = client.try_create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1,
+        &0,
+        &((1i128 << 64) + 100),
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+
+    let result = client.try_create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &2,
+        &0,
+        &i128::MAX,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+
+    let result = client.try_create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1,
+        &(u64::MAX - 10),
+        &100,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
+}
+
+/// Depositing while the stream runs extends it without changing what has
+/// already accrued.
+#[test]
+fn test_rate_stream_deposit_while_running_extends_end_time() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+
+    let stream_id = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &10,
+        &0,
+        &1000,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 50);
+    client.deposit(&stream_id, &sender, &500);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.total_amount, 1500);
+    assert_eq!(stream.end_time, 150);
+    assert_eq!(client.claimable(&stream_id, &50), 500);
+    assert_eq!(client.claimable(&stream_id, &100), 1000);
+    assert_eq!(client.claimable(&stream_id, &150), 1500);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&contract_id), 1500);
+}
+
+/// Once depleted, the idle period is not owed; a new deposit restarts
+/// accrual from the deposit time.
+#[test]
+fn test_rate_stream_deposit_after_depletion_restarts_accrual() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1300);
+
+    let stream_id = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &10,
+        &0,
+        &1000,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    client.deposit(&stream_id, &sender, &300);
+
+    assert_eq!(client.get_stream(&stream_id).end_time, 230);
+    assert_eq!(client.claimable(&stream_id, &200), 1000);
+    assert_eq!(client.claimable(&stream_id, &210), 1100);
+    assert_eq!(client.claimable(&stream_id, &230), 1300);
+
+    env.ledger().with_mut(|l| l.timestamp = 230);
+    client.claim(&stream_id, &recipient, &1300);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 1300);
+}
+
+/// Cancel refunds the part of the deposit that has not been streamed yet.
+#[test]
+fn test_rate_stream_cancel_refunds_remaining_deposit() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &10,
+        &0,
+        &1000,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 30);
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 700);
+    assert_eq!(client.claimable(&stream_id, &9999), 300);
+}
+
+#[test]
+fn test_deposit_on_linear_stream_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_deposit(&stream_id, &sender, &1000);
+    assert_eq!(result, Err(Ok(Error::UnsupportedStreamKind)));
+}
+
+#[test]
+fn test_deposit_on_canceled_rate_stream_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+    let stream_id = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &10,
+        &0,
+        &1000,
+        &false,
+        &Cancelability::Always,
+    );
+    client.cancel(&stream_id, &sender, &false);
+    let result = client.try_deposit(&stream_id, &sender, &1000);
+    assert_eq!(result, Err(Ok(Error::StreamCanceled)));
+}
+
+#[test]
+fn test_deposit_insufficient_balance_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+    let stream_id = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1,
+        &0,
+        &1000,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_deposit(&stream_id, &sender, &1000);
+    assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+}
+
+// -----------------------------------------------------------------
+// PAUSE AND RESUME
+// -----------------------------------------------------------------
+
+/// Pause at t=300 for 200 seconds: vesting freezes at 300 during the pause
+/// and the stream ends 200 seconds later than planned.
+#[test]
+fn test_pause_freezes_vesting_and_resume_shifts_end_time() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    client.pause(&stream_id, &sender);
+    assert_eq!(client.get_stream(&stream_id).paused_at, Some(300));
+    assert_eq!(client.claimable(&stream_id, &400), 300);
+    assert_eq!(client.claimable(&stream_id, &9999), 300);
+
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    client.resume(&stream_id, &sender);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.paused_at, None);
+    assert_eq!(stream.start_time, 200);
+    assert_eq!(stream.end_time, 1200);
+    assert_eq!(client.claimable(&stream_id, &500), 300);
+    assert_eq!(client.claimable(&stream_id, &700), 500);
+    assert_eq!(client.claimable(&stream_id, &1200), 1000);
+}
+
+/// The recipient can still claim what vested before the pause.
+#[test]
+fn test_pause_recipient_can_claim_vested_while_paused() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.pause(&stream_id, &sender);
+    env.ledger().with_mut(|l| l.timestamp = 800);
+    client.claim(&stream_id, &recipient, &400);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 400);
+    assert_eq!(client.claimable(&stream_id, &800), 0);
+}
+
+/// Cancel while paused splits at the pause point.
+#[test]
+fn test_pause_cancel_while_paused_splits_at_pause_time() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 250);
+    client.pause(&stream_id, &sender);
+    env.ledger().with_mut(|l| l.timestamp = 700);
+    client.cancel(&stream_id, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 750);
+    assert_eq!(client.claimable(&stream_id, &9999), 250);
+}
+
+/// Tranche timestamps move with the pause as well.
+#[test]
+fn test_pause_resume_shifts_tranches() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: 500,
+        },
+        Tranche {
+            timestamp: 200,
+            amount: 500,
+        },
+    ];
+    let stream_id = client.create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 150);
+    client.pause(&stream_id, &sender);
+    env.ledger().with_mut(|l| l.timestamp = 250);
+    client.resume(&stream_id, &sender);
+
+    assert_eq!(client.claimable(&stream_id, &250), 500);
+    assert_eq!(client.claimable(&stream_id, &299), 500);
+    assert_eq!(client.claimable(&stream_id, &300), 1000);
+}
+
+/// Pausing before start_time only delays the stream by the part of the
+/// pause that reached past start_time.
+#[test]
+fn test_pause_before_start_only_counts_time_after_start() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &500,
+        &1500,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.pause(&stream_id, &sender);
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    client.resume(&stream_id, &sender);
+    assert_eq!(client.get_stream(&stream_id).start_time, 500);
+
+    client.pause(&stream_id, &sender);
+    env.ledger().with_mut(|l| l.timestamp = 600);
+    client.resume(&stream_id, &sender);
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.start_time, 600);
+    assert_eq!(stream.end_time, 1600);
+}
+
+/// A deposit into a paused rate stream does not restart accrual.
+#[test]
+fn test_pause_rate_stream_deposit_while_paused() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+
+    let stream_id = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &10,
+        &0,
+        &1000,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 40);
+    client.pause(&stream_id, &sender);
+    env.ledger().with_mut(|l| l.timestamp = 60);
+    client.deposit(&stream_id, &sender, &500);
+    assert_eq!(client.claimable(&stream_id, &90), 400);
+
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    client.resume(&stream_id, &sender);
+    assert_eq!(client.get_stream(&stream_id).end_time, 210);
+    assert_eq!(client.claimable(&stream_id, &150), 900);
+    assert_eq!(client.claimable(&stream_id, &210), 1500);
+}
+
+#[test]
+fn test_pause_twice_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.pause(&stream_id, &sender);
+    let result = client.try_pause(&stream_id, &sender);
+    assert_eq!(result, Err(Ok(Error::StreamPaused)));
+}
+
+#[test]
+fn test_resume_without_pause_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_resume(&stream_id, &sender);
+    assert_eq!(result, Err(Ok(Error::StreamNotPaused)));
+}
+
+#[test]
+fn test_pause_resume_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    client.pause(&stream_id, &sender);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Paused")).into_val(&env)
+    );
+    let event_data: StreamPaused = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamPaused {
+            stream_id,
+            sender: sender.clone(),
+            paused_at: 100,
+        }
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 150);
+    client.resume(&stream_id, &sender);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Resumed")).into_val(&env)
+    );
+    let event_data: StreamResumed = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamResumed {
+            stream_id,
+            sender,
+            end_time: 1050,
+        }
+    );
+}
+
+// -----------------------------------------------------------------
+// TOP UP
+// -----------------------------------------------------------------
+
+/// Top up 1000 and extend to t=2000 at the halfway point of a 0..1000
+/// stream: the 500 already vested are kept and the remaining 1500 stream
+/// over t=500..2000.
+#[test]
+fn test_top_up_preserves_vested_amount() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    client.claim(&stream_id, &recipient, &200);
+    client.top_up(&stream_id, &sender, &1000, &Some(2000));
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.total_amount, 2000);
+    assert_eq!(stream.end_time, 2000);
+    assert_eq!(stream.claimed_amount, 200);
+    assert_eq!(client.claimable(&stream_id, &500), 300);
+    assert_eq!(client.claimable(&stream_id, &1250), 1050);
+    assert_eq!(client.claimable(&stream_id, &2000), 1800);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&contract_id), 1800);
+}
+
+/// Extending without new funds slows the stream down from now on.
+#[test]
+fn test_top_up_extend_only() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 600);
+    client.top_up(&stream_id, &sender, &0, &Some(1400));
+
+    assert_eq!(client.claimable(&stream_id, &600), 600);
+    assert_eq!(client.claimable(&stream_id, &1000), 800);
+    assert_eq!(client.claimable(&stream_id, &1400), 1000);
+}
+
+/// Before start_time the schedule is simply enlarged.
+#[test]
+fn test_top_up_before_start_keeps_schedule() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.top_up(&stream_id, &sender, &500, &None);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.start_time, 100);
+    assert_eq!(stream.end_time, 1100);
+    assert_eq!(client.claimable(&stream_id, &600), 750);
+}
+
+/// Topping up during the cliff keeps the cliff and the accrual since
+/// start_time.
+#[test]
+fn test_top_up_during_cliff_keeps_cliff() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &Some(500),
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    client.top_up(&stream_id, &sender, &1000, &None);
+
+    assert_eq!(client.get_stream(&stream_id).cliff_time, Some(500));
+    assert_eq!(client.claimable(&stream_id, &499), 0);
+    assert_eq!(client.claimable(&stream_id, &500), 1000);
+}
+
+#[test]
+fn test_top_up_event() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.top_up(&stream_id, &sender, &500, &Some(1500));
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("ToppedUp")).into_val(&env)
+    );
+    let event_data: StreamToppedUp = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamToppedUp {
+            stream_id,
+            sender,
+            amount: 500,
+            total_amount: 1500,
+            end_time: 1500,
+        }
+    );
+}
+
+#[test]
+fn test_top_up_shortening_end_time_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_top_up(&stream_id, &sender, &500, &Some(900));
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
+}
+
+#[test]
+fn test_top_up_tranched_stream_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: 1000,
+        },
+    ];
+    let stream_id = client.create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_top_up(&stream_id, &sender, &500, &None);
+    assert_eq!(result, Err(Ok(Error::UnsupportedStreamKind)));
+}
+
+#[test]
+fn test_top_up_insufficient_balance_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_top_up(&stream_id, &sender, &1000, &None);
+    assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+}
+
+// -----------------------------------------------------------------
+// RESCHEDULE
+// -----------------------------------------------------------------
+
+/// Moving the start later shifts end_time and the cliff by the same amount.
+#[test]
+fn test_reschedule_moves_whole_schedule() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &Some(300),
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 50);
+    client.reschedule(&stream_id, &sender, &600);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.start_time, 600);
+    assert_eq!(stream.end_time, 1600);
+    assert_eq!(stream.cliff_time, Some(800));
+    assert_eq!(client.claimable(&stream_id, &799), 0);
+    assert_eq!(client.claimable(&stream_id, &800), 200);
+}
+
+/// The start can also be brought forward, as long as it is not in the past.
+#[test]
+fn test_reschedule_earlier_start() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 600,
+            amount: 400,
+        },
+        Tranche {
+            timestamp: 700,
+            amount: 600,
+        },
+    ];
+    let stream_id = client.create_tranched_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &500,
+        &tranches,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    client.reschedule(&stream_id, &sender, &200);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.end_time, 400);
+    assert_eq!(client.claimable(&stream_id, &300), 400);
+    assert_eq!(client.claimable(&stream_id, &400), 1000);
+}
+
+#[test]
+fn test_reschedule_event() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.reschedule(&stream_id, &sender, &400);
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Resched")).into_val(&env)
+    );
+    let event_data: StreamRescheduled = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamRescheduled {
+            stream_id,
+            sender,
+            old_start_time: 100,
+            start_time: 400,
+            end_time: 1400,
+        }
+    );
+}
+
+#[test]
+fn test_reschedule_after_start_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    let result = client.try_reschedule(&stream_id, &sender, &500);
+    assert_eq!(result, Err(Ok(Error::StreamStarted)));
+}
+
+#[test]
+fn test_reschedule_into_past_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    env.ledger().with_mut(|l| l.timestamp = 50);
+    let result = client.try_reschedule(&stream_id, &sender, &20);
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
+}
+
+#[test]
+fn test_reschedule_past_max_timestamp_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_reschedule(&stream_id, &sender, &(u64::MAX - 500));
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
+    assert_eq!(client.get_stream(&stream_id).start_time, 100);
+}
+
+#[test]
+fn test_reschedule_wrong_sender_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_reschedule(&stream_id, &recipient, &500);
+    assert_eq!(result, Err(Ok(Error::SenderMismatch)));
+}
+
+// -----------------------------------------------------------------
+// AMENDMENTS
+// -----------------------------------------------------------------
+
+/// Sender proposes a raise to 2000 ending at t=2000; the recipient accepts
+/// at t=500. The 500 vested under the old terms are kept and the remaining
+/// 1500 stream over t=500..2000.
+#[test]
+fn test_amendment_sender_raise_accepted_by_recipient() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.propose_amendment(&stream_id, &sender, &2000, &2000, &600);
+
+    // the raise is escrowed up front
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 0);
+    assert_eq!(token_client.balance(&contract_id), 2000);
+    assert!(client.get_amendment(&stream_id).is_some());
+
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    client.accept_amendment(&stream_id, &recipient);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.total_amount, 2000);
+    assert_eq!(stream.end_time, 2000);
+    assert_eq!(client.get_amendment(&stream_id), None);
+    assert_eq!(client.claimable(&stream_id, &500), 500);
+    assert_eq!(client.claimable(&stream_id, &1250), 1250);
+    assert_eq!(client.claimable(&stream_id, &2000), 2000);
+}
+
+/// Recipient agrees to a cut; the sender accepts and gets the difference
+/// back immediately.
+#[test]
+fn test_amendment_cut_refunds_sender_on_accept() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    client.propose_amendment(&stream_id, &recipient, &600, &1000, &300);
+    client.accept_amendment(&stream_id, &sender);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 400);
+    assert_eq!(client.claimable(&stream_id, &200), 200);
+    assert_eq!(client.claimable(&stream_id, &600), 400);
+    assert_eq!(client.claimable(&stream_id, &1000), 600);
+}
+
+/// Canceling a proposal hands the escrowed raise back to the sender.
+#[test]
+fn test_amendment_cancel_returns_escrow() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.propose_amendment(&stream_id, &sender, &1500, &1000, &100);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 0);
+
+    client.cancel_amendment(&stream_id, &recipient);
+    assert_eq!(token_client.balance(&sender), 500);
+    assert_eq!(client.get_amendment(&stream_id), None);
+    assert_eq!(client.get_stream(&stream_id).total_amount, 1000);
+}
+
+/// Canceling returns the escrow of a pending raise along with the refund.
+#[test]
+fn test_cancel_releases_pending_amendment() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.propose_amendment(&stream_id, &sender, &1500, &1000, &900);
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    client.cancel(&stream_id, &sender, &true);
+
+    assert_eq!(client.get_amendment(&stream_id), None);
+    let token_client = token::Client::new(&env, &token);
+    // 700 refund + 500 escrow
+    assert_eq!(token_client.balance(&sender), 1200);
+    assert_eq!(token_client.balance(&recipient), 300);
+    assert_eq!(token_client.balance(&contract_id), 0);
+}
+
+/// Renouncing returns the escrow of a pending raise along with the refund.
+#[test]
+fn test_renounce_releases_pending_amendment() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.propose_amendment(&stream_id, &sender, &1500, &1000, &900);
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    client.renounce(&stream_id, &recipient, &true);
+
+    assert_eq!(client.get_amendment(&stream_id), None);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 1200);
+    assert_eq!(token_client.balance(&recipient), 300);
+    assert_eq!(token_client.balance(&contract_id), 0);
+}
+
+#[test]
+fn test_amendment_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.propose_amendment(&stream_id, &recipient, &1000, &1500, &100);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("AmendProp")).into_val(&env)
+    );
+    let event_data: AmendmentProposed = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        AmendmentProposed {
+            stream_id,
+            proposer: recipient.clone(),
+            total_amount: 1000,
+            end_time: 1500,
+            expires_at: 100,
+        }
+    );
+
+    client.accept_amendment(&stream_id, &sender);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Amended")).into_val(&env)
+    );
+    let event_data: StreamAmended = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamAmended {
+            stream_id,
+            accepted_by: sender,
+            total_amount: 1000,
+            end_time: 1500,
+        }
+    );
+}
+
+#[test]
+fn test_amendment_accept_after_expiry_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.propose_amendment(&stream_id, &recipient, &1000, &1500, &100);
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    let result = client.try_accept_amendment(&stream_id, &sender);
+    assert_eq!(result, Err(Ok(Error::AmendmentExpired)));
+}
+
+#[test]
+fn test_amendment_proposer_cannot_accept_own_proposal() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.propose_amendment(&stream_id, &sender, &800, &1500, &100);
+    let result = client.try_accept_amendment(&stream_id, &sender);
+    assert_eq!(result, Err(Ok(Error::NotCounterparty)));
+}
+
+#[test]
+fn test_amendment_cannot_cut_below_vested() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    client.propose_amendment(&stream_id, &sender, &300, &1500, &900);
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    let result = client.try_accept_amendment(&stream_id, &recipient);
+    assert_eq!(result, Err(Ok(Error::BelowVestedAmount)));
+}
+
+/// A raise the sender cannot fund fails with a typed error, whether the
+/// sender proposes it or accepts the recipient's proposal.
+#[test]
+fn test_amendment_raise_insufficient_balance_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_propose_amendment(&stream_id, &sender, &2000, &1000, &500);
+    assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+
+    client.propose_amendment(&stream_id, &recipient, &2000, &1000, &500);
+    let result = client.try_accept_amendment(&stream_id, &sender);
+    assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+}
+
+// -----------------------------------------------------------------
+// RECIPIENT TRANSFER
+// -----------------------------------------------------------------
+
+/// The new recipient can claim both the unclaimed vested balance and
+/// everything that vests afterwards; the old one can no longer claim.
+#[test]
+fn test_transfer_recipient_moves_claim_rights() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &true,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    client.claim(&stream_id, &recipient, &100);
+    client.transfer_recipient(&stream_id, &recipient, &buyer);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.recipient, buyer);
+    assert!(stream.transferable);
+
+    client.claim(&stream_id, &buyer, &200);
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    client.claim(&stream_id, &buyer, &700);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 100);
+    assert_eq!(token_client.balance(&buyer), 900);
+}
+
+#[test]
+fn test_transfer_recipient_old_recipient_cannot_claim() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &true,
+        &Cancelability::Always,
+    );
+    client.transfer_recipient(&stream_id, &recipient, &buyer);
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    let result = client.try_claim(&stream_id, &recipient, &100);
+    assert_eq!(result, Err(Ok(Error::RecipientMismatch)));
+}
+
+#[test]
+fn test_transfer_recipient_non_transferable_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_transfer_recipient(&stream_id, &recipient, &buyer);
+    assert_eq!(result, Err(Ok(Error::StreamNotTransferable)));
+}
+
+#[test]
+fn test_transfer_recipient_by_non_recipient_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &true,
+        &Cancelability::Always,
+    );
+    let result = client.try_transfer_recipient(&stream_id, &sender, &sender);
+    assert_eq!(result, Err(Ok(Error::RecipientMismatch)));
+}
+
+#[test]
+fn test_transfer_recipient_event_and_drops_old_recipient_proposal() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &true,
+        &Cancelability::Always,
+    );
+
+    client.propose_amendment(&stream_id, &recipient, &800, &2000, &500);
+    client.transfer_recipient(&stream_id, &recipient, &buyer);
+    assert_eq!(client.get_amendment(&stream_id), None);
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Transfer")).into_val(&env)
+    );
+    let event_data: RecipientTransferred = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        RecipientTransferred {
+            stream_id,
+            from: recipient,
+            to: buyer,
+        }
+    );
+}
+
+// -----------------------------------------------------------------
+// SENDER TRANSFER
+// -----------------------------------------------------------------
+
+/// After the two steps the new sender can cancel and receives the refund;
+/// the old sender no longer can.
+#[test]
+fn test_sender_transfer_moves_cancel_authority_and_refund() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.propose_sender_transfer(&stream_id, &sender, &treasury);
+    assert_eq!(
+        client.get_pending_sender(&stream_id),
+        Some(treasury.clone())
+    );
+    assert_eq!(client.get_stream(&stream_id).sender, sender);
+
+    client.accept_sender_transfer(&stream_id, &treasury);
+    assert_eq!(client.get_pending_sender(&stream_id), None);
+    assert_eq!(client.get_stream(&stream_id).sender, treasury);
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.cancel(&stream_id, &treasury, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&treasury), 600);
+    assert_eq!(token_client.balance(&sender), 0);
+}
+
+/// An amendment proposed by the old sender is dropped and its escrow goes
+/// back to the old sender; one proposed by the recipient stays open.
+#[test]
+fn test_sender_transfer_drops_old_sender_proposal() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &3000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let other_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.propose_amendment(&stream_id, &sender, &1500, &1000, &500);
+    client.propose_amendment(&other_id, &recipient, &800, &1000, &500);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 500);
+
+    client.propose_sender_transfer(&stream_id, &sender, &treasury);
+    client.accept_sender_transfer(&stream_id, &treasury);
+    client.propose_sender_transfer(&other_id, &sender, &treasury);
+    client.accept_sender_transfer(&other_id, &treasury);
+
+    assert_eq!(client.get_amendment(&stream_id), None);
+    assert_eq!(token_client.balance(&sender), 1000);
+    assert_eq!(token_client.balance(&contract_id), 2000);
+    assert_eq!(
+        client.try_accept_amendment(&stream_id, &recipient),
+        Err(Ok(Error::NoPendingAmendment))
+    );
+
+    client.accept_amendment(&other_id, &treasury);
+    assert_eq!(client.get_stream(&other_id).total_amount, 800);
+    assert_eq!(token_client.balance(&treasury), 200);
+}
+
+#[test]
+fn test_sender_transfer_old_sender_cannot_cancel() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.propose_sender_transfer(&stream_id, &sender, &treasury);
+    client.accept_sender_transfer(&stream_id, &treasury);
+    let result = client.try_cancel(&stream_id, &sender, &false);
+    assert_eq!(result, Err(Ok(Error::SenderMismatch)));
+}
+
+#[test]
+fn test_sender_transfer_accept_by_other_address_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.propose_sender_transfer(&stream_id, &sender, &treasury);
+    let result = client.try_accept_sender_transfer(&stream_id, &recipient);
+    assert_eq!(result, Err(Ok(Error::SenderMismatch)));
+}
+
+#[test]
+fn test_sender_transfer_accept_without_proposal_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_accept_sender_transfer(&stream_id, &treasury);
+    assert_eq!(result, Err(Ok(Error::NoPendingSenderTransfer)));
+}
+
+#[test]
+fn test_sender_transfer_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.propose_sender_transfer(&stream_id, &sender, &treasury);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("SndrProp")).into_val(&env)
+    );
+    let event_data: SenderTransferProposed = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        SenderTransferProposed {
+            stream_id,
+            sender: sender.clone(),
+            new_sender: treasury.clone(),
+        }
+    );
+
+    client.accept_sender_transfer(&stream_id, &treasury);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("SndrXfer")).into_val(&env)
+    );
+    let event_data: SenderTransferred = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        SenderTransferred {
+            stream_id,
+            from: sender,
+            to: treasury,
+        }
+    );
+}
+
+// -----------------------------------------------------------------
+// CANCELABILITY
+// -----------------------------------------------------------------
+
+/// An irrevocable stream rejects cancel with a typed error and keeps
+/// streaming.
+#[test]
+fn test_non_cancelable_stream_rejects_cancel() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Never,
+    );
+    assert_eq!(
+        client.get_stream(&stream_id).cancelability,
+        Cancelability::Never
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    let result = client.try_cancel(&stream_id, &sender, &false);
+    assert_eq!(result, Err(Ok(Error::StreamNotCancelable)));
+    assert!(!client.get_stream(&stream_id).canceled);
+    assert_eq!(client.claimable(&stream_id, &1000), 1000);
+}
+
+/// Cancelable only after t=600: rejected before, allowed from then on.
+#[test]
+fn test_cancel_after_timestamp() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::After(600),
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 599);
+    let result = client.try_cancel(&stream_id, &sender, &false);
+    assert_eq!(result, Err(Ok(Error::StreamNotCancelable)));
+
+    env.ledger().with_mut(|l| l.timestamp = 600);
+    client.cancel(&stream_id, &sender, &false);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 400);
+}
+
+/// Pausing would let the sender hold back an irrevocable grant, so it is
+/// rejected as well.
+#[test]
+fn test_non_cancelable_stream_rejects_pause() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Never,
+    );
+
+    let result = client.try_pause(&stream_id, &sender);
+    assert_eq!(result, Err(Ok(Error::StreamNotCancelable)));
+}
+
+/// Rescheduling is gated like cancel, otherwise the sender could push an
+/// irrevocable stream out forever before it starts.
+#[test]
+fn test_non_cancelable_stream_rejects_reschedule() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    let never = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Never,
+    );
+    let after = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1100,
+        &None,
+        &0,
+        &false,
+        &Cancelability::After(50),
+    );
+
+    let result = client.try_reschedule(&never, &sender, &(u64::MAX / 2));
+    assert_eq!(result, Err(Ok(Error::StreamNotCancelable)));
+    let result = client.try_reschedule(&after, &sender, &500);
+    assert_eq!(result, Err(Ok(Error::StreamNotCancelable)));
+
+    env.ledger().with_mut(|l| l.timestamp = 50);
+    client.reschedule(&after, &sender, &500);
+    assert_eq!(client.get_stream(&after).start_time, 500);
+}
+
+// -----------------------------------------------------------------
+// RENOUNCE
+// -----------------------------------------------------------------
+
+/// Renounce at t=400 after claiming 100: the recipient receives the other
+/// 300 vested tokens and the sender gets the unvested 600 back.
+#[test]
+fn test_renounce_pays_vested_and_refunds_rest() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    client.claim(&stream_id, &recipient, &100);
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.renounce(&stream_id, &recipient, &true);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 400);
+    assert_eq!(token_client.balance(&sender), 600);
+    assert_eq!(token_client.balance(&contract_id), 0);
+
+    let stream = client.get_stream(&stream_id);
+    assert!(stream.canceled);
+    assert_eq!(stream.claimed_amount, 400);
+    assert_eq!(client.claimable(&stream_id, &9999), 0);
+}
+
+/// Renouncing without claiming forfeits the vested balance to the sender.
+#[test]
+fn test_renounce_forfeiting_vested_refunds_everything_unclaimed() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Never,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    client.claim(&stream_id, &recipient, &100);
+    env.ledger().with_mut(|l| l.timestamp = 1500);
+    client.renounce(&stream_id, &recipient, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 100);
+    assert_eq!(token_client.balance(&sender), 900);
+    assert_eq!(client.claimable(&stream_id, &9999), 0);
+}
+
+#[test]
+fn test_renounce_event() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 250);
+    client.renounce(&stream_id, &recipient, &true);
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Renounced")).into_val(&env)
+    );
+    let event_data: StreamRenounced = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamRenounced {
+            stream_id,
+            recipient,
+            recipient_amount: 250,
+            sender_refund: 750,
+            fee: 0,
+        }
+    );
+}
+
+#[test]
+fn test_renounce_by_sender_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_renounce(&stream_id, &sender, &true);
+    assert_eq!(result, Err(Ok(Error::RecipientMismatch)));
+}
+
+#[test]
+fn test_renounce_canceled_stream_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.cancel(&stream_id, &sender, &false);
+    let result = client.try_renounce(&stream_id, &recipient, &true);
+    assert_eq!(result, Err(Ok(Error::StreamCanceled)));
+}
+
+// -----------------------------------------------------------------
+// WITHDRAW MAX
+// -----------------------------------------------------------------
+
+/// Everything vested at t=300 goes to the destination, not the recipient.
+#[test]
+fn test_withdraw_max_sends_all_vested_to_destination() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let destination = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    client.claim(&stream_id, &recipient, &50);
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    let receipt = client.withdraw_max(&stream_id, &recipient, &destination);
+    assert_eq!(
+        receipt,
+        ClaimReceipt {
+            amount: 250,
+            fee: 0,
+            claimed_amount: 300,
+        }
+    );
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&destination), 250);
+    assert_eq!(token_client.balance(&recipient), 50);
+    assert_eq!(client.get_stream(&stream_id).claimed_amount, 300);
+    assert_eq!(client.claimable(&stream_id, &300), 0);
+}
+
+/// Nothing vested yet: a zero receipt and no transfer or event.
+#[test]
+fn test_withdraw_max_before_start_returns_zero_receipt() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    let events_before = env.events().all().len();
+    let receipt = client.withdraw_max(&stream_id, &recipient, &recipient);
+    assert_eq!(
+        receipt,
+        ClaimReceipt {
+            amount: 0,
+            fee: 0,
+            claimed_amount: 0,
+        }
+    );
+    assert_eq!(env.events().all().len(), events_before);
+}
+
+#[test]
+fn test_withdraw_max_emits_claimed_event() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 2000);
+    client.withdraw_max(&stream_id, &recipient, &recipient);
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Claimed")).into_val(&env)
+    );
+    let event_data: StreamClaimed = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamClaimed {
+            stream_id,
+            recipient,
+            amount: 1000,
+            fee: 0,
+        }
+    );
+}
+
+#[test]
+fn test_withdraw_max_by_non_recipient_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    let result = client.try_withdraw_max(&stream_id, &sender, &sender);
+    assert_eq!(result, Err(Ok(Error::RecipientMismatch)));
+}
+
+// -----------------------------------------------------------------
+// BATCH CLAIM
+// -----------------------------------------------------------------
+
+/// Three streams across two tokens: every stream is settled and the
+/// recipient is paid the per-token sum.
+#[test]
+fn test_claim_many_aggregates_per_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_a = create_token(&env, &admin);
+    let token_b = create_token(&env, &admin);
+    token::StellarAssetClient::new(&env, &token_a).mint(&sender, &3000);
+    token::StellarAssetClient::new(&env, &token_b).mint(&sender, &1000);
+
+    let first = client.create_stream(
+        &sender,
+        &recipient,
+        &token_a,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let second = client.create_stream(
+        &sender,
+        &recipient,
+        &token_a,
+        &2000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let third = client.create_stream(
+        &sender,
+        &recipient,
+        &token_b,
+        &1000,
+        &0,
+        &500,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 250);
+    let amounts = client.claim_many(&recipient, &vec![&env, first, second, third]);
+    assert_eq!(amounts, vec![&env, 250, 500, 500]);
+
+    assert_eq!(token::Client::new(&env, &token_a).balance(&recipient), 750);
+    assert_eq!(token::Client::new(&env, &token_b).balance(&recipient), 500);
+    assert_eq!(client.get_stream(&first).claimed_amount, 250);
+    assert_eq!(client.get_stream(&second).claimed_amount, 500);
+    assert_eq!(client.get_stream(&third).claimed_amount, 500);
+}
+
+/// Streams with nothing claimable are skipped, and a repeated id is only
+/// paid once.
+#[test]
+fn test_claim_many_skips_empty_and_duplicate_streams() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    let started = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let pending = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &500,
+        &1500,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    let events_before = env.events().all().len();
+    let amounts = client.claim_many(&recipient, &vec![&env, started, pending, started]);
+    assert_eq!(amounts, vec![&env, 100, 0, 0]);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 100);
+    assert_eq!(client.get_stream(&started).claimed_amount, 100);
+
+    // one Claimed event for the stream that paid, plus the token transfer
+    assert_eq!(env.events().all().len(), events_before + 2);
+}
+
+#[test]
+fn test_claim_many_emits_event_per_stream() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+    let first = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let second = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.claim_many(&recipient, &vec![&env, first, second]);
+
+    let claimed: std::vec::Vec<StreamClaimed> = env
+        .events()
+        .all()
+        .iter()
+        .filter(|e| e.1 == (symbol_short!("Stream"), symbol_short!("Claimed")).into_val(&env))
+        .map(|e| e.2.into_val(&env))
+        .collect();
+    assert_eq!(
+        claimed,
+        std::vec![
+            StreamClaimed {
+                stream_id: first,
+                recipient: recipient.clone(),
+                amount: 400,
+                fee: 0,
+            },
+            StreamClaimed {
+                stream_id: second,
+                recipient,
+                amount: 400,
+                fee: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_claim_many_with_foreign_stream_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let other = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+    let mine = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let theirs = client.create_stream(
+        &sender,
+        &other,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    let result = client.try_claim_many(&recipient, &vec![&env, mine, theirs]);
+    assert_eq!(result, Err(Ok(Error::RecipientMismatch)));
+}
+
+// -----------------------------------------------------------------
+// BATCH CREATE
+// -----------------------------------------------------------------
+
+/// Payroll run: two streams funded by a single escrow transfer.
+#[test]
+fn test_create_streams_escrows_sum_once() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &5000);
+
+    let entries = vec![
+        &env,
+        StreamEntry {
+            recipient: alice.clone(),
+            total_amount: 1000,
+            start_time: 0,
+            end_time: 1000,
+        },
+        StreamEntry {
+            recipient: bob.clone(),
+            total_amount: 3000,
+            start_time: 100,
+            end_time: 400,
+        },
+    ];
+    let events_before = env.events().all().len();
+    let ids = client.create_streams(&sender, &token, &entries, &false, &Cancelability::Always);
+    assert_eq!(ids, vec![&env, 1, 2]);
+    assert_eq!(client.get_next_stream_id(), 2);
+
+    // one token transfer plus one Created event per stream
+    assert_eq!(env.events().all().len(), events_before + 3);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 1000);
+    assert_eq!(token_client.balance(&contract_id), 4000);
+
+    let first = client.get_stream(&1);
+    assert_eq!(first.recipient, alice);
+    assert_eq!(first.total_amount, 1000);
+    assert_eq!(first.kind, StreamKind::Linear);
+    let second = client.get_stream(&2);
+    assert_eq!(second.recipient, bob);
+    assert_eq!(second.start_time, 100);
+    assert_eq!(second.end_time, 400);
+
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    assert_eq!(client.claimable(&2, &200), 1000);
+}
+
+/// A bad entry anywhere in the batch rejects the whole batch.
+#[test]
+fn test_create_streams_invalid_entry_rejects_batch() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &5000);
+
+    let entries = vec![
+        &env,
+        StreamEntry {
+            recipient: recipient.clone(),
+            total_amount: 1000,
+            start_time: 0,
+            end_time: 1000,
+        },
+        StreamEntry {
+            recipient,
+            total_amount: 1000,
+            start_time: 500,
+            end_time: 500,
+        },
+    ];
+    let result =
+        client.try_create_streams(&sender, &token, &entries, &false, &Cancelability::Always);
+    assert_eq!(result, Err(Ok(Error::InvalidTimeRange)));
+
+    assert_eq!(client.get_next_stream_id(), 0);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 5000);
+}
+
+#[test]
+fn test_create_streams_insufficient_balance_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1500);
+
+    let entry = StreamEntry {
+        recipient,
+        total_amount: 1000,
+        start_time: 0,
+        end_time: 1000,
+    };
+    let result = client.try_create_streams(
+        &sender,
+        &token,
+        &vec![&env, entry.clone(), entry],
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+}
+
+#[test]
+fn test_create_streams_empty_batch_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let token = create_token(&env, &admin);
+
+    let result = client.try_create_streams(
+        &sender,
+        &token,
+        &Vec::new(&env),
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::EmptyBatch)));
+}
+
+#[test]
+fn test_create_streams_total_overflow_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let entry = StreamEntry {
+        recipient,
+        total_amount: i128::MAX / 2 + 1,
+        start_time: 0,
+        end_time: 1000,
+    };
+    let result = client.try_create_streams(
+        &sender,
+        &token,
+        &vec![&env, entry.clone(), entry],
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+}
+
+// -----------------------------------------------------------------
+// BATCH CANCEL
+// -----------------------------------------------------------------
+
+/// Cancel three streams over two tokens at t=250; refunds are summed per
+/// token and recipients keep what had vested.
+#[test]
+fn test_cancel_many_aggregates_refunds_per_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_a = create_token(&env, &admin);
+    let token_b = create_token(&env, &admin);
+    token::StellarAssetClient::new(&env, &token_a).mint(&sender, &3000);
+    token::StellarAssetClient::new(&env, &token_b).mint(&sender, &1000);
+
+    let first = client.create_stream(
+        &sender,
+        &recipient,
+        &token_a,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let second = client.create_stream(
+        &sender,
+        &recipient,
+        &token_a,
+        &2000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let third = client.create_stream(
+        &sender,
+        &recipient,
+        &token_b,
+        &1000,
+        &0,
+        &500,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 250);
+    client.cancel_many(&sender, &vec![&env, first, second, third], &false);
+
+    let token_a_client = token::Client::new(&env, &token_a);
+    let token_b_client = token::Client::new(&env, &token_b);
+    assert_eq!(token_a_client.balance(&sender), 750 + 1500);
+    assert_eq!(token_b_client.balance(&sender), 500);
+
+    for stream_id in [first, second, third] {
+        assert!(client.get_stream(&stream_id).canceled);
+    }
+    assert_eq!(client.claimable(&first, &9999), 250);
+    assert_eq!(client.claimable(&second, &9999), 500);
+    assert_eq!(client.claimable(&third, &9999), 500);
+}
+
+/// One Canceled event per stream; an already canceled stream is skipped.
+#[test]
+fn test_cancel_many_emits_event_per_stream_and_skips_canceled() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    let first = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let second = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    client.cancel(&first, &sender, &false);
+    client.cancel_many(&sender, &vec![&env, first, second], &false);
+
+    let canceled: std::vec::Vec<StreamCanceled> = env
+        .events()
+        .all()
+        .iter()
+        .filter(|e| e.1 == (symbol_short!("Stream"), symbol_short!("Canceled")).into_val(&env))
+        .map(|e| e.2.into_val(&env))
+        .collect();
+    assert_eq!(
+        canceled,
+        std::vec![
+            StreamCanceled {
+                stream_id: first,
+                sender: sender.clone(),
+                sender_refund: 900,
+                recipient_amount: 0,
+                fee: 0,
+            },
+            StreamCanceled {
+                stream_id: second,
+                sender: sender.clone(),
+                sender_refund: 900,
+                recipient_amount: 0,
+                fee: 0,
+            },
+        ]
+    );
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 900 + 900);
+}
+
+#[test]
+fn test_cancel_many_respects_cancelability() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+    let revocable = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let irrevocable = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Never,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 100);
+    let result = client.try_cancel_many(&sender, &vec![&env, revocable, irrevocable], &false);
+    assert_eq!(result, Err(Ok(Error::StreamNotCancelable)));
+    assert!(!client.get_stream(&revocable).canceled);
+}
+
+#[test]
+fn test_cancel_many_with_foreign_stream_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let other_sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&other_sender, &1000);
+    let stream_id = client.create_stream(
+        &other_sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let result = client.try_cancel_many(&sender, &vec![&env, stream_id], &false);
+    assert_eq!(result, Err(Ok(Error::SenderMismatch)));
+}
+
+// -----------------------------------------------------------------
+// CLAIM OPERATORS
+// -----------------------------------------------------------------
+
+/// An approved operator triggers the claim; the tokens land with the
+/// recipient.
+#[test]
+fn test_operator_claim_pays_recipient() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.approve_operator(&recipient, &operator);
+    assert!(client.is_operator(&recipient, &operator));
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.claim(&stream_id, &operator, &300);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 300);
+    assert_eq!(token_client.balance(&operator), 0);
+    assert_eq!(client.get_stream(&stream_id).claimed_amount, 300);
+
+    let last_event = env.events().all().last().unwrap();
+    let event_data: StreamClaimed = last_event.2.into_val(&env);
+    assert_eq!(event_data.recipient, recipient);
+}
+
+#[test]
+fn test_operator_approve_and_revoke_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let recipient = Address::generate(&env);
+    let operator = Address::generate(&env);
+
+    client.approve_operator(&recipient, &operator);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("OpApprove")).into_val(&env)
+    );
+    let approved: OperatorApproved = last_event.2.into_val(&env);
+    assert_eq!(
+        approved,
+        OperatorApproved {
+            recipient: recipient.clone(),
+            operator: operator.clone(),
+        }
+    );
+
+    client.revoke_operator(&recipient, &operator);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("OpRevoke")).into_val(&env)
+    );
+    let revoked: OperatorRevoked = last_event.2.into_val(&env);
+    assert_eq!(
+        revoked,
+        OperatorRevoked {
+            recipient: recipient.clone(),
+            operator: operator.clone(),
+        }
+    );
+    assert!(!client.is_operator(&recipient, &operator));
+}
+
+#[test]
+fn test_revoked_operator_cannot_claim() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.approve_operator(&recipient, &operator);
+    client.revoke_operator(&recipient, &operator);
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    let result = client.try_claim(&stream_id, &operator, &100);
+    assert_eq!(result, Err(Ok(Error::RecipientMismatch)));
+}
+
+/// Approval is per recipient: it does not reach another recipient's
+/// streams.
+#[test]
+fn test_operator_cannot_claim_for_other_recipient() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let other_recipient = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &other_recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.approve_operator(&recipient, &operator);
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    let result = client.try_claim(&stream_id, &operator, &100);
+    assert_eq!(result, Err(Ok(Error::RecipientMismatch)));
+}
+
+#[test]
+fn test_revoke_unknown_operator_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let recipient = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let result = client.try_revoke_operator(&recipient, &operator);
+    assert_eq!(result, Err(Ok(Error::OperatorNotApproved)));
+}
+
+// -----------------------------------------------------------------
+// SETTLE ON CANCEL
+// -----------------------------------------------------------------
+
+/// Cancel at t=400 after a claim of 100 with `settle`: the recipient gets
+/// the remaining 300 vested, the sender the unvested 600, and the stream
+/// ends fully settled.
+#[test]
+fn test_cancel_with_settle_pays_both_sides() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    client.claim(&stream_id, &recipient, &100);
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.cancel(&stream_id, &sender, &true);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 400);
+    assert_eq!(token_client.balance(&sender), 600);
+    assert_eq!(token_client.balance(&contract_id), 0);
+
+    let stream = client.get_stream(&stream_id);
+    assert!(stream.canceled);
+    assert!(stream.settled);
+    assert_eq!(stream.claimed_amount, 400);
+    assert_eq!(client.claimable(&stream_id, &9999), 0);
+
+    let last_event = env.events().all().last().unwrap();
+    let event_data: StreamCanceled = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamCanceled {
+            stream_id,
+            sender,
+            sender_refund: 600,
+            recipient_amount: 300,
+            fee: 0,
+        }
+    );
+}
+
+/// Without `settle` the stream only becomes settled once the recipient
+/// claims what was left in escrow.
+#[test]
+fn test_cancel_without_settle_is_settled_after_final_claim() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    client.cancel(&stream_id, &sender, &false);
+    assert!(!client.get_stream(&stream_id).settled);
+
+    client.claim(&stream_id, &recipient, &300);
+    assert!(client.get_stream(&stream_id).settled);
+}
+
+/// Nothing vested before the start: settling pays the recipient nothing and
+/// the stream is settled straight away.
+#[test]
+fn test_cancel_with_settle_before_start() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &100,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.cancel(&stream_id, &sender, &true);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&sender), 1000);
+    assert_eq!(token_client.balance(&recipient), 0);
+    assert!(client.get_stream(&stream_id).settled);
+}
+
+#[test]
+fn test_cancel_many_with_settle_pays_each_recipient() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &3000);
+    let first = client.create_stream(
+        &sender,
+        &alice,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let second = client.create_stream(
+        &sender,
+        &alice,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let third = client.create_stream(
+        &sender,
+        &bob,
+        &token,
+        &1000,
+        &0,
+        &500,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 250);
+    client.cancel_many(&sender, &vec![&env, first, second, third], &true);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&alice), 500);
+    assert_eq!(token_client.balance(&bob), 500);
+    assert_eq!(token_client.balance(&sender), 750 + 750 + 500);
+    assert_eq!(token_client.balance(&contract_id), 0);
+    for stream_id in [first, second, third] {
+        assert!(client.get_stream(&stream_id).settled);
+    }
+}
+
+// -----------------------------------------------------------------
+// STORAGE TTL
+// -----------------------------------------------------------------
+
+const YEAR: u64 = 365 * 24 * 60 * 60;
+
+fn stream_ttl(env: &Env, contract_id: &Address, stream_id: u64) -> u32 {
+    env.as_contract(contract_id, || {
+        env.storage()
+            .persistent()
+            .get_ttl(&DataKey::Stream(stream_id))
+    })
+}
+
+/// A one-year stream is kept live until its end, capped at the network's
+/// maximum TTL.
+#[test]
+fn test_create_stream_extends_ttl_to_cover_end_time() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &YEAR,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    let ledgers_to_end = (YEAR / LEDGER_SECONDS) as u32;
+    let ttl = stream_ttl(&env, &contract_id, stream_id);
+    assert!(ttl >= ledgers_to_end);
+    assert!(ttl <= env.ledger().get().max_entry_ttl);
+}
+
+/// Far past the default persistent TTL the stream is still readable and
+/// claimable, because the entry was extended on creation.
+#[test]
+fn test_stream_survives_past_default_ttl() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    let default_ttl = env.ledger().get().min_persistent_entry_ttl;
+    env.ledger().with_mut(|l| {
+        l.sequence_number += default_ttl * 10;
+        l.timestamp = 500;
+    });
+
+    assert_eq!(client.get_stream(&stream_id).total_amount, 1000);
+    assert_eq!(client.claimable(&stream_id, &500), 500);
+}
+
+/// Anyone can bump a stream; this keeps it live for the grace period after
+/// `end_time` even once the original TTL is nearly used up.
+#[test]
+fn test_bump_stream_extends_ttl() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| {
+        l.sequence_number += STREAM_TTL_GRACE - 10;
+        l.timestamp = 2000;
+    });
+    assert!(stream_ttl(&env, &contract_id, stream_id) <= 10 + 200);
+
+    client.bump_stream(&stream_id);
+    assert_eq!(stream_ttl(&env, &contract_id, stream_id), STREAM_TTL_GRACE);
+    assert_eq!(client.claimable(&stream_id, &2000), 1000);
+}
+
+#[test]
+fn test_stream_entrypoints_extend_instance_ttl() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    let instance_ttl = env.as_contract(&contract_id, || env.storage().instance().get_ttl());
+    assert_eq!(instance_ttl, INSTANCE_BUMP_AMOUNT);
+}
+
+fn entry_ttl(env: &Env, contract_id: &Address, key: &DataKey) -> u32 {
+    env.as_contract(contract_id, || env.storage().persistent().get_ttl(key))
+}
+
+/// Moves `days` forward, keeping the token contract itself live.
+fn advance_days(env: &Env, token: &Address, days: u32) {
+    let ledgers = (days + 1) * DAY_IN_LEDGERS;
+    env.as_contract(token, || {
+        env.storage().instance().extend_ttl(ledgers, ledgers)
+    });
+    env.ledger().with_mut(|l| {
+        l.sequence_number += days * DAY_IN_LEDGERS;
+        l.timestamp += days as u64 * 24 * 60 * 60;
+    });
+}
+
+/// The id counter is extended along with the instance, so it stays live
+/// through a period without new streams as long as the contract is used.
+#[test]
+fn test_next_stream_id_survives_while_streams_are_bumped() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &YEAR,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    for _ in 0..2 {
+        advance_days(&env, &token, 20);
+        client.bump_stream(&stream_id);
+        assert_eq!(
+            entry_ttl(&env, &contract_id, &DataKey::NextStreamId),
+            ENTRY_BUMP_AMOUNT
+        );
+    }
+
+    // reading an archived entry would fail here
+    assert_eq!(client.get_next_stream_id(), 1);
+}
+
+/// Operator approvals, pending amendments and pending sender transfers are
+/// extended whenever they are written or read.
+#[test]
+fn test_side_entries_extend_ttl_when_used() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let new_sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &YEAR,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    client.approve_operator(&recipient, &operator);
+    client.propose_amendment(&stream_id, &recipient, &900, &YEAR, &YEAR);
+    client.propose_sender_transfer(&stream_id, &sender, &new_sender);
+
+    let operator_key = DataKey::Operator(recipient.clone(), operator.clone());
+    let amendment_key = DataKey::Amendment(stream_id);
+    let pending_key = DataKey::PendingSender(stream_id);
+    for key in [&operator_key, &amendment_key, &pending_key] {
+        assert_eq!(entry_ttl(&env, &contract_id, key), ENTRY_BUMP_AMOUNT);
+    }
+
+    // past the first extension, kept alive by being used
+    for _ in 0..2 {
+        advance_days(&env, &token, 20);
+        client.claim(&stream_id, &operator, &1);
+        assert!(client.get_amendment(&stream_id).is_some());
+        assert_eq!(
+            client.get_pending_sender(&stream_id),
+            Some(new_sender.clone())
+        );
+    }
+    for key in [&operator_key, &amendment_key, &pending_key] {
+        assert_eq!(entry_ttl(&env, &contract_id, key), ENTRY_BUMP_AMOUNT);
+    }
+
+    client.accept_sender_transfer(&stream_id, &new_sender);
+    assert_eq!(client.get_stream(&stream_id).sender, new_sender);
+}
+
+#[test]
+fn test_bump_unknown_stream_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let result = client.try_bump_stream(&7);
+    assert_eq!(result, Err(Ok(Error::StreamNotFound)));
+}
+
+// -----------------------------------------------------------------
+// ADMIN AND UPGRADES
+// -----------------------------------------------------------------
+
+/// Uploads this contract built with the `next-version` feature, standing in
+/// for the next release.
+fn upload_v2_wasm(env: &Env) -> BytesN<32> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test_fixtures/stream_v2.wasm");
+    let wasm = std::fs::read(path)
+        .unwrap_or_else(|_| panic!("{path} is missing, run scripts/build-upgrade-fixture.sh"));
+    env.deployer()
+        .upload_contract_wasm(Bytes::from_slice(env, &wasm))
+}
+
+#[test]
+fn test_initialize_sets_admin_once() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+
+    client.initialize(&admin);
+    assert_eq!(client.get_admin(), admin);
+    assert_eq!(client.version(), CONTRACT_VERSION);
+
+    let other = Address::generate(&env);
+    let result = client.try_initialize(&other);
+    assert_eq!(result, Err(Ok(Error::AlreadyInitialized)));
+    assert_eq!(client.get_admin(), admin);
+}
+
+#[test]
+fn test_admin_entrypoints_before_initialize_fail() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let hash = BytesN::from_array(&env, &[0; 32]);
+    assert_eq!(client.try_upgrade(&hash), Err(Ok(Error::NotInitialized)));
+    assert_eq!(client.try_migrate(), Err(Ok(Error::NotInitialized)));
+    assert_eq!(client.try_get_admin(), Err(Ok(Error::NotInitialized)));
+}
+
+#[test]
+fn test_set_admin_hands_over_control() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    let new_admin = Address::generate(&env);
+
+    client.initialize(&admin);
+    client.set_admin(&new_admin);
+    assert_eq!(
+        env.auths(),
+        std::vec![(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    contract_id.clone(),
+                    Symbol::new(&env, "set_admin"),
+                    (new_admin.clone(),).into_val(&env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )]
+    );
+    assert_eq!(client.get_admin(), new_admin);
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("AdminSet")).into_val(&env)
+    );
+    let event_data: AdminChanged = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        AdminChanged {
+            from: admin,
+            to: new_admin,
+        }
+    );
+}
+
+/// Upgrading swaps the code in place: the new build answers `version`, and
+/// streams and escrowed funds are untouched.
+#[test]
+#[ignore = "needs scripts/build-upgrade-fixture.sh"]
+fn test_upgrade_switches_to_new_build_and_keeps_streams() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.initialize(&admin);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(client.version(), 1);
+
+    let new_wasm_hash = upload_v2_wasm(&env);
+    client.upgrade(&new_wasm_hash);
+    assert_eq!(
+        env.auths(),
+        std::vec![(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    contract_id.clone(),
+                    Symbol::new(&env, "upgrade"),
+                    (new_wasm_hash.clone(),).into_val(&env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )]
+    );
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Upgraded")).into_val(&env)
+    );
+    let event_data: ContractUpgraded = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        ContractUpgraded {
+            admin,
+            new_wasm_hash,
+        }
+    );
+
+    assert_eq!(client.version(), 2);
+    assert_eq!(client.get_stream(&stream_id).total_amount, 1000);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&contract_id), 1000);
+}
+
+/// After an upgrade `migrate` moves the stored version forward once, and
+/// streams created by the old build keep working on the new one.
+#[test]
+#[ignore = "needs scripts/build-upgrade-fixture.sh"]
+fn test_upgrade_then_migrate_keeps_streams_claimable() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.initialize(&admin);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    env.ledger().with_mut(|l| l.timestamp = 200);
+    client.claim(&stream_id, &recipient, &200);
+
+    client.upgrade(&upload_v2_wasm(&env));
+    client.migrate();
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("Migrated")).into_val(&env)
+    );
+    let event_data: ContractMigrated = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        ContractMigrated {
+            from_version: 1,
+            to_version: 2,
+        }
+    );
+    assert_eq!(client.try_migrate(), Err(Ok(Error::AlreadyMigrated)));
+
+    env.ledger().with_mut(|l| l.timestamp = 600);
+    assert_eq!(client.claim(&stream_id, &recipient, &400), 400);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.claimed_amount, 600);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 600);
+    assert_eq!(token_client.balance(&contract_id), 400);
+}
+
+/// Without an upgrade there is nothing to migrate.
+#[test]
+fn test_migrate_without_upgrade_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+
+    client.initialize(&admin);
+    assert_eq!(client.try_migrate(), Err(Ok(Error::AlreadyMigrated)));
+}
+
+// -----------------------------------------------------------------
+// CONTRACT PAUSE
+// -----------------------------------------------------------------
+
+/// Every way of creating streams or adding funds and terms is blocked.
+#[test]
+fn test_contract_pause_blocks_creation_and_funding() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &10_000);
+
+    client.initialize(&admin);
+    let linear = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let rate = client.create_rate_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1,
+        &0,
+        &1000,
+        &false,
+        &Cancelability::Always,
+    );
+    client.propose_amendment(&linear, &recipient, &900, &1000, &500);
+
+    client.pause_contract(&false, &false);
+
+    assert_eq!(
+        client.try_create_stream(
+            &sender,
+            &recipient,
+            &token,
+            &1000,
+            &0,
+            &1000,
+            &None,
+            &0,
+            &false,
+            &Cancelability::Always,
+        ),
+        Err(Ok(Error::Paused))
+    );
+    let entry = StreamEntry {
+        recipient: recipient.clone(),
+        total_amount: 1000,
+        start_time: 0,
+        end_time: 1000,
+    };
+    assert_eq!(
+        client.try_create_streams(
+            &sender,
+            &token,
+            &vec![&env, entry],
+            &false,
+            &Cancelability::Always,
+        ),
+        Err(Ok(Error::Paused))
+    );
+    let tranches = vec![
+        &env,
+        Tranche {
+            timestamp: 100,
+            amount: 1000,
+        },
+    ];
+    assert_eq!(
+        client.try_create_tranched_stream(
+            &sender,
+            &recipient,
+            &token,
+            &1000,
+            &0,
+            &tranches,
+            &false,
+            &Cancelability::Always,
+        ),
+        Err(Ok(Error::Paused))
+    );
+    let segments = vec![
+        &env,
+        Segment {
+            end_time: 100,
+            amount: 1000,
+        },
+    ];
+    assert_eq!(
+        client.try_create_segmented_stream(
+            &sender,
+            &recipient,
+            &token,
+            &1000,
+            &0,
+            &segments,
+            &false,
+            &Cancelability::Always,
+        ),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_create_curve_stream(
+            &sender,
+            &recipient,
+            &token,
+            &1000,
+            &0,
+            &1000,
+            &WAD,
+            &false,
+            &Cancelability::Always,
+        ),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_create_rate_stream(
+            &sender,
+            &recipient,
+            &token,
+            &1,
+            &0,
+            &1000,
+            &false,
+            &Cancelability::Always,
+        ),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_top_up(&linear, &sender, &100, &None),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_deposit(&rate, &sender, &100),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_propose_amendment(&linear, &sender, &1100, &1000, &500),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_accept_amendment(&linear, &sender),
+        Err(Ok(Error::Paused))
+    );
+
+    assert_eq!(client.get_next_stream_id(), 2);
+}
+
+/// With only creation paused, recipients and senders can still claim and
+/// cancel, and vesting keeps running.
+#[test]
+fn test_contract_pause_without_flags_keeps_claims_and_cancels_open() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    client.initialize(&admin);
+    let first = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let second = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.pause_contract(&false, &false);
+    assert_eq!(
+        client.get_contract_pause(),
+        Some(ContractPause {
+            claims: false,
+            cancels: false,
+        })
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 300);
+    client.claim(&first, &recipient, &100);
+    client.withdraw_max(&first, &recipient, &recipient);
+    client.claim_many(&recipient, &vec![&env, first]);
+    client.cancel(&second, &sender, &false);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 300);
+}
+
+/// Claims blocked: every claim entrypoint fails, as do cancels and
+/// renounces that would pay the recipient; vesting carries on, and
+/// everything accrued is claimable after the unpause.
+#[test]
+fn test_contract_pause_blocks_claims_but_vesting_accrues() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.initialize(&admin);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.pause_contract(&true, &false);
+    env.ledger().with_mut(|l| l.timestamp = 600);
+
+    assert_eq!(
+        client.try_claim(&stream_id, &recipient, &100),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_withdraw_max(&stream_id, &recipient, &recipient),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_claim_many(&recipient, &vec![&env, stream_id]),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(client.claimable(&stream_id, &600), 600);
+
+    // paying out the vested balance through a cancel counts as a claim
+    assert_eq!(
+        client.try_cancel(&stream_id, &sender, &true),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_cancel_many(&sender, &vec![&env, stream_id], &true),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_renounce(&stream_id, &recipient, &true),
+        Err(Ok(Error::Paused))
+    );
+    let stream = client.get_stream(&stream_id);
+    assert!(!stream.canceled);
+    assert_eq!(stream.end_time, 1000);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 0);
+
+    client.unpause_contract();
+    assert_eq!(client.get_contract_pause(), None);
+    assert_eq!(client.claim(&stream_id, &recipient, &600), 600);
+
+    // cancels that leave the vested balance in escrow stay open
+    client.pause_contract(&true, &false);
+    client.cancel(&stream_id, &sender, &true);
+    assert_eq!(token_client.balance(&sender), 400);
+    assert_eq!(token_client.balance(&recipient), 600);
+}
+
+/// Cancels blocked: cancel, cancel_many and renounce all fail; per-stream
+/// pause, transfers and views are unaffected.
+#[test]
+fn test_contract_pause_blocks_cancels() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let new_recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.initialize(&admin);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &true,
+        &Cancelability::Always,
+    );
+
+    client.pause_contract(&false, &true);
+    env.ledger().with_mut(|l| l.timestamp = 200);
+
+    assert_eq!(
+        client.try_cancel(&stream_id, &sender, &true),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_cancel_many(&sender, &vec![&env, stream_id], &false),
+        Err(Ok(Error::Paused))
+    );
+    assert_eq!(
+        client.try_renounce(&stream_id, &recipient, &true),
+        Err(Ok(Error::Paused))
+    );
+
+    client.pause(&stream_id, &sender);
+    client.resume(&stream_id, &sender);
+    client.transfer_recipient(&stream_id, &recipient, &new_recipient);
+    client.bump_stream(&stream_id);
+    assert!(!client.get_stream(&stream_id).canceled);
+}
+
+#[test]
+fn test_contract_pause_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+
+    client.pause_contract(&true, &false);
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("CPaused")).into_val(&env)
+    );
+    let event_data: ContractPaused = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        ContractPaused {
+            admin: admin.clone(),
+            claims: true,
+            cancels: false,
+        }
+    );
+
+    client.unpause_contract();
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("CUnpaused")).into_val(&env)
+    );
+    let event_data: ContractUnpaused = last_event.2.into_val(&env);
+    assert_eq!(event_data, ContractUnpaused { admin });
+
+    assert_eq!(client.try_unpause_contract(), Err(Ok(Error::NotPaused)));
+}
+
+#[test]
+fn test_contract_pause_requires_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    assert_eq!(
+        client.try_pause_contract(&true, &true),
+        Err(Ok(Error::NotInitialized))
+    );
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    client.pause_contract(&true, &true);
+    assert_eq!(
+        env.auths(),
+        std::vec![(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    contract_id.clone(),
+                    Symbol::new(&env, "pause_contract"),
+                    (true, true).into_val(&env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )]
+    );
+}
+
+// -----------------------------------------------------------------
+// PROTOCOL FEE
+// -----------------------------------------------------------------
+
+#[test]
+fn test_fee_on_claim_goes_to_treasury() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.initialize(&admin);
+    client.set_fee(&100, &treasury);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    assert_eq!(client.get_stream(&stream_id).fee_bps, 100);
+
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    client.claim(&stream_id, &recipient, &500);
+
+    let last_event = env.events().all().last().unwrap();
+    let event_data: StreamClaimed = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamClaimed {
+            stream_id,
+            recipient: recipient.clone(),
+            amount: 500,
+            fee: 5,
+        }
+    );
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 495);
+    assert_eq!(token_client.balance(&treasury), 5);
+    assert_eq!(token_client.balance(&contract_id), 500);
+
+    let stream = client.get_stream(&stream_id);
+    assert_eq!(stream.claimed_amount, 500);
+    assert_eq!(stream.fees_paid, 5);
+}
+
+/// Many small claims pay the same fee as one large one.
+#[test]
+fn test_fee_not_rounded_away_by_small_claims() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.initialize(&admin);
+    client.set_fee(&100, &treasury);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    // 1% of 20 rounds down to 0 on its own
+    for _ in 0..50 {
+        client.claim(&stream_id, &recipient, &20);
+    }
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 990);
+    assert_eq!(token_client.balance(&treasury), 10);
+    assert_eq!(client.get_stream(&stream_id).fees_paid, 10);
+}
+
+#[test]
+fn test_fee_applies_to_withdraw_max_and_settle() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let destination = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.initialize(&admin);
+    client.set_fee(&200, &treasury);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 250);
+    let receipt = client.withdraw_max(&stream_id, &recipient, &destination);
+    assert_eq!(
+        receipt,
+        ClaimReceipt {
+            amount: 250,
+            fee: 5,
+            claimed_amount: 250,
+        }
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    client.cancel(&stream_id, &sender, &true);
+
+    let last_event = env.events().all().last().unwrap();
+    let event_data: StreamCanceled = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamCanceled {
+            stream_id,
+            sender: sender.clone(),
+            sender_refund: 500,
+            recipient_amount: 250,
+            fee: 5,
+        }
+    );
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&destination), 245);
+    assert_eq!(token_client.balance(&recipient), 245);
+    assert_eq!(token_client.balance(&sender), 500);
+    assert_eq!(token_client.balance(&treasury), 10);
+    assert_eq!(token_client.balance(&contract_id), 0);
+
+    let stream = client.get_stream(&stream_id);
+    assert!(stream.settled);
+    assert_eq!(stream.fees_paid, 10);
+}
+
+#[test]
+fn test_fee_applies_to_renounce() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &1000);
+
+    client.initialize(&admin);
+    client.set_fee(&200, &treasury);
+
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 400);
+    client.renounce(&stream_id, &recipient, &true);
+
+    let last_event = env.events().all().last().unwrap();
+    let event_data: StreamRenounced = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        StreamRenounced {
+            stream_id,
+            recipient: recipient.clone(),
+            recipient_amount: 400,
+            sender_refund: 600,
+            fee: 8,
+        }
+    );
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 392);
+    assert_eq!(token_client.balance(&sender), 600);
+    assert_eq!(token_client.balance(&treasury), 8);
+    assert_eq!(token_client.balance(&contract_id), 0);
+}
+
+#[test]
+fn test_fee_applies_to_batch_claim() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    client.initialize(&admin);
+    client.set_fee(&100, &treasury);
+
+    let entries = vec![
+        &env,
+        StreamEntry {
+            recipient: recipient.clone(),
+            total_amount: 1000,
+            start_time: 0,
+            end_time: 1000,
+        },
+        StreamEntry {
+            recipient: recipient.clone(),
+            total_amount: 1000,
+            start_time: 0,
+            end_time: 1000,
+        },
+    ];
+    let ids = client.create_streams(&sender, &token, &entries, &false, &Cancelability::Always);
+
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    let amounts = client.claim_many(&recipient, &ids);
+    assert_eq!(amounts, vec![&env, 1000, 1000]);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 1980);
+    assert_eq!(token_client.balance(&treasury), 20);
+}
+
+#[test]
+fn test_fee_applies_to_cancel_many() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    client.initialize(&admin);
+    client.set_fee(&100, &treasury);
+
+    let entries = vec![
+        &env,
+        StreamEntry {
+            recipient: recipient.clone(),
+            total_amount: 1000,
+            start_time: 0,
+            end_time: 1000,
+        },
+        StreamEntry {
+            recipient: recipient.clone(),
+            total_amount: 1000,
+            start_time: 0,
+            end_time: 1000,
+        },
+    ];
+    let ids = client.create_streams(&sender, &token, &entries, &false, &Cancelability::Always);
+
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    client.cancel_many(&sender, &ids, &true);
+
+    let canceled: std::vec::Vec<StreamCanceled> = env
+        .events()
+        .all()
+        .iter()
+        .filter(|e| e.1 == (symbol_short!("Stream"), symbol_short!("Canceled")).into_val(&env))
+        .map(|e| e.2.into_val(&env))
+        .collect();
+    assert_eq!(canceled.len(), 2);
+    for event in canceled {
+        assert_eq!(event.recipient_amount, 500);
+        assert_eq!(event.fee, 5);
+    }
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 990);
+    assert_eq!(token_client.balance(&sender), 1000);
+    assert_eq!(token_client.balance(&treasury), 10);
+}
+
+#[test]
+fn test_token_fee_override() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let other_token = create_token(&env, &admin);
+
+    client.initialize(&admin);
+    assert_eq!(client.get_fee_config(), None);
+    assert_eq!(client.get_fee_bps(&token), 0);
+
+    assert_eq!(
+        client.try_set_token_fee(&token, &Some(50)),
+        Err(Ok(Error::FeeNotConfigured))
+    );
+
+    client.set_fee(&100, &treasury);
+    client.set_token_fee(&token, &Some(0));
+    assert_eq!(
+        client.get_fee_config(),
+        Some(FeeConfig {
+            fee_bps: 100,
+            treasury: treasury.clone(),
+        })
+    );
+    assert_eq!(client.get_fee_bps(&token), 0);
+    assert_eq!(client.get_fee_bps(&other_token), 100);
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("TokenFee")).into_val(&env)
+    );
+    let event_data: TokenFeeUpdated = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        TokenFeeUpdated {
+            token: token.clone(),
+            fee_bps: Some(0),
+        }
+    );
+
+    client.set_token_fee(&token, &None);
+    assert_eq!(client.get_fee_bps(&token), 100);
+}
+
+/// Changing the fee only affects streams created afterwards.
+#[test]
+fn test_fee_rate_is_fixed_at_creation() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    let token_admin = token::StellarAssetClient::new(&env, &token);
+    token_admin.mint(&sender, &2000);
+
+    client.initialize(&admin);
+    let free_stream = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+
+    client.set_fee(&100, &treasury);
+    let stream_id = client.create_stream(
+        &sender,
+        &recipient,
+        &token,
+        &1000,
+        &0,
+        &1000,
+        &None,
+        &0,
+        &false,
+        &Cancelability::Always,
+    );
+    let last_event = env.events().all().last().unwrap();
+    let event_data: StreamCreated = last_event.2.into_val(&env);
+    assert_eq!(event_data.fee_bps, 100);
+
+    client.set_fee(&1000, &treasury);
+
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    client.claim(&free_stream, &recipient, &1000);
+    client.claim(&stream_id, &recipient, &1000);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&recipient), 1990);
+    assert_eq!(token_client.balance(&treasury), 10);
+}
+
+#[test]
+fn test_fee_above_cap_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let token = create_token(&env, &admin);
+
+    client.initialize(&admin);
+    assert_eq!(
+        client.try_set_fee(&1001, &treasury),
+        Err(Ok(Error::FeeTooHigh))
+    );
+
+    client.set_fee(&1000, &treasury);
+    assert_eq!(
+        client.try_set_token_fee(&token, &Some(1001)),
+        Err(Ok(Error::FeeTooHigh))
+    );
+}
+
+#[test]
+fn test_set_fee_requires_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, StellarStreamContract);
+    let client = StellarStreamContractClient::new(&env, &contract_id);
+
+    let treasury = Address::generate(&env);
+    assert_eq!(
+        client.try_set_fee(&100, &treasury),
+        Err(Ok(Error::NotInitialized))
+    );
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    client.set_fee(&100, &treasury);
+    assert_eq!(
+        env.auths(),
+        std::vec![(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    contract_id.clone(),
+                    Symbol::new(&env, "set_fee"),
+                    (100u32, treasury.clone()).into_val(&env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )]
+    );
+
+    let last_event = env.events().all().last().unwrap();
+    assert_eq!(
+        last_event.1,
+        (symbol_short!("Stream"), symbol_short!("FeeSet")).into_val(&env)
+    );
+    let event_data: FeeUpdated = last_event.2.into_val(&env);
+    assert_eq!(
+        event_data,
+        FeeUpdated {
+            fee_bps: 100,
+            treasury,
+        }
+    );
+}
@@ -0,0 +1,33 @@
+#!/bin/bash
+
+# Build the upgrade test fixture for the StellarStream contract
+#
+# Compiles the contract from source with the `next-version` feature, which
+# makes it report version 2, and copies the wasm to
+# contracts/test_fixtures/stream_v2.wasm. The upgrade tests upload it,
+# upgrade a contract running the current build to it, and run `migrate`.
+#
+# Requires the `wasm32v1-none` target (Rust 1.84 or newer):
+#   rustup target add wasm32v1-none
+#
+# Usage:
+#   ./scripts/build-upgrade-fixture.sh
+#   cd contracts && cargo test -- --include-ignored
+
+set -e
+
+CONTRACTS_DIR="$(cd "$(dirname "$0")/../contracts" && pwd)"
+TARGET_DIR="$CONTRACTS_DIR/target/next-version"
+
+cd "$CONTRACTS_DIR"
+
+cargo build \
+    --target wasm32v1-none \
+    --release \
+    --features next-version \
+    --target-dir "$TARGET_DIR"
+
+mkdir -p test_fixtures
+cp "$TARGET_DIR/wasm32v1-none/release/stellar_stream.wasm" test_fixtures/stream_v2.wasm
+
+echo "Wrote contracts/test_fixtures/stream_v2.wasm"
@@ -3,14 +3,19 @@
 # Deploy StellarStream contract to Stellar testnet
 # 
 # Required environment variables:
-#   SECRET_KEY - Stellar account secret key for deployment
+#   SECRET_KEY    - Stellar account secret key for deployment
+#   ADMIN_ADDRESS - Public key (G...) of SECRET_KEY; becomes the contract admin
 #
 # Optional environment variables:
 #   NETWORK_PASSPHRASE - Network passphrase (defaults to testnet)
 #   RPC_URL - RPC endpoint URL (defaults to testnet)
 #
 # Usage:
-#   SECRET_KEY="S..." ./scripts/deploy.sh
+#   SECRET_KEY="S..." ADMIN_ADDRESS="G..." ./scripts/deploy.sh
+#
+# `initialize` is sent right after the deploy and the stored admin is checked
+# afterwards. Anyone can call `initialize` on a fresh contract, so if another
+# account got there first the script fails and the contract must not be used.
 
 set -e
 
@@ -34,6 +39,12 @@ if [ -z "$SECRET_KEY" ]; then
     exit 1
 fi
 
+if [ -z "$ADMIN_ADDRESS" ]; then
+    echo -e "${RED}Error: ADMIN_ADDRESS environment variable is required${NC}"
+    echo "Please set ADMIN_ADDRESS to the public key of SECRET_KEY"
+    exit 1
+fi
+
 # Check if soroban-cli is installed
 if ! command -v soroban &> /dev/null; then
     echo -e "${RED}Error: soroban-cli is not installed${NC}"
@@ -96,6 +107,33 @@ if [ ${#CONTRACT_ID} -ne 56 ]; then
     exit 1
 fi
 
+# Initialize straight away, before anyone else can claim the admin role
+echo -e "${YELLOW}Initializing contract...${NC}"
+if ! soroban contract invoke \
+    --id "$CONTRACT_ID" \
+    --source-account "$SECRET_KEY" \
+    --network testnet \
+    --network-passphrase "$NETWORK_PASSPHRASE" \
+    --rpc-url "$RPC_URL" \
+    -- initialize --admin "$ADMIN_ADDRESS"; then
+    echo -e "${RED}Error: initialize failed${NC}"
+    echo "The contract may have been initialized by someone else. Do not use $CONTRACT_ID; deploy again."
+    exit 1
+fi
+
+STORED_ADMIN=$(soroban contract invoke \
+    --id "$CONTRACT_ID" \
+    --source-account "$SECRET_KEY" \
+    --network testnet \
+    --network-passphrase "$NETWORK_PASSPHRASE" \
+    --rpc-url "$RPC_URL" \
+    -- get_admin | tr -d '"[:space:]')
+if [ "$STORED_ADMIN" != "$ADMIN_ADDRESS" ]; then
+    echo -e "${RED}Error: contract admin is $STORED_ADMIN, expected $ADMIN_ADDRESS${NC}"
+    echo "Do not use $CONTRACT_ID; deploy again."
+    exit 1
+fi
+
 # Save contract ID to file
 echo "$CONTRACT_ID" > "$CONTRACT_ID_FILE"
 